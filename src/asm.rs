@@ -0,0 +1,518 @@
+//! Text form of a program is a list of opcodes, one per line:
+//! a mnemonic followed by comma separated arguments.
+//!
+//! ```text
+//! function main
+//!     move r1, 0xE1EE7          ; integer register and constant
+//!     jump_zero skip, [16]      ; label name and memory address
+//!     move [r2 + r3*8 - 16], r1 ; memory at base + index * scale + displacement
+//!     print_string "Hello!\n"   ; quoted string
+//!     label skip
+//!     return
+//! end_function
+//! ```
+//!
+//! Data blocks are defined outside of functions and referred by `@name`:
+//!
+//! ```text
+//! read_only_data greeting, u8, "Hello!\n"
+//! data table, i32le, {1, -2, 0x30}
+//! function main
+//!     move r1, @table           ; address of table
+//!     move r2, [@table + 4]     ; 64-bit word at table + 4
+//!     return
+//! end_function
+//! ```
+//!
+//! Names that look like registers (`r1`, `f2`) or special floating
+//! constants (`inf`, `NaN`) must be quoted.
+
+use std::collections::HashMap;
+
+use error::Error;
+use opcode::{Argument, Opcode};
+use operand::OperandType;
+
+/// Keeps names parsed from text or bytecode.
+/// Opcodes refer to names as &'static str, so every unique name is leaked once.
+#[derive(Default)]
+pub struct Interner {
+	names: HashMap<String, &'static str>,
+}
+
+impl Interner {
+	pub fn intern(&mut self, name: &str) -> &'static str {
+		if let Some(&interned) = self.names.get(name) {
+			return interned;
+		}
+		let interned: &'static str = Box::leak(name.to_owned().into_boxed_str());
+		self.names.insert(name.to_owned(), interned);
+		interned
+	}
+}
+
+/// Parse a text program into a list of opcodes.
+pub fn assemble(source: &str) -> Result<Vec<Opcode>, Error> {
+	let mut interner = Interner::default();
+	let mut program: Vec<Opcode> = Vec::new();
+	for (line_number, line) in source.lines().enumerate() {
+		let line = strip_comment(line).trim();
+		if line.is_empty() {
+			continue;
+		}
+		let opcode = parse_line(line, &mut interner)
+				.map_err(|e| Error::AssemblyFailed(line_number + 1, Box::new(e)))?;
+		program.push(opcode);
+	}
+	Ok(program)
+}
+
+/// Convert a list of opcodes into a text program.
+pub fn disassemble(program: &[Opcode]) -> String {
+	let mut result = String::new();
+	for opcode in program {
+		let (mnemonic, args) = opcode.to_parts();
+		match *opcode {
+			Opcode::Registers(..) | Opcode::Data(..) | Opcode::ReadOnlyData(..) |
+			Opcode::FunctionStart(_) | Opcode::FunctionEnd => {},
+			_ => result.push('\t'),
+		}
+		result.push_str(mnemonic);
+		let args: Vec<String> = args.iter().map(format_argument).collect();
+		if !args.is_empty() {
+			result.push(' ');
+			result.push_str(&args.join(", "));
+		}
+		result.push('\n');
+		if let Opcode::FunctionEnd = *opcode {
+			result.push('\n');
+		}
+	}
+	result
+}
+
+fn strip_comment(line: &str) -> &str {
+	let mut in_string = false;
+	let mut escaped = false;
+	for (i, c) in line.char_indices() {
+		match c {
+			_ if escaped      => escaped = false,
+			'\\' if in_string => escaped = true,
+			'"'               => in_string = !in_string,
+			';' if !in_string => return &line[..i],
+			_ => {},
+		}
+	}
+	line
+}
+
+fn parse_line(line: &str, interner: &mut Interner) -> Result<Opcode, Error> {
+	let (mnemonic, rest) = match line.find(char::is_whitespace) {
+		Some(i) => (&line[..i], &line[i..]),
+		None    => (line, ""),
+	};
+	let mut args: Vec<Argument> = Vec::new();
+	for token in split_arguments(rest)? {
+		args.push(parse_argument(token, interner)?);
+	}
+	Opcode::from_parts(mnemonic, &args)
+}
+
+/// Split by commas, that are not inside of a string, brackets or braces.
+fn split_arguments(text: &str) -> Result<Vec<&str>, Error> {
+	if text.trim().is_empty() {
+		return Ok(Vec::new());
+	}
+	let mut result: Vec<&str> = Vec::new();
+	let mut in_string = false;
+	let mut escaped = false;
+	let mut depth: usize = 0;
+	let mut start: usize = 0;
+	for (i, c) in text.char_indices() {
+		match c {
+			_ if escaped      => escaped = false,
+			'\\' if in_string => escaped = true,
+			'"'               => in_string = !in_string,
+			'[' | '{' if !in_string => depth += 1,
+			']' | '}' if !in_string => depth = depth.checked_sub(1).ok_or(Error::InvalidArgument)?,
+			',' if !in_string && depth == 0 => {
+				result.push(text[start..i].trim());
+				start = i + 1;
+			},
+			_ => {},
+		}
+	}
+	result.push(text[start..].trim());
+	if result.iter().any(|token| token.is_empty()) {
+		return Err(Error::InvalidArgument);
+	}
+	Ok(result)
+}
+
+fn parse_argument(token: &str, interner: &mut Interner) -> Result<Argument, Error> {
+	if token.starts_with('"') {
+		return Ok(Argument::Name(interner.intern(&parse_string(token)?)));
+	}
+	if token.starts_with('{') && token.ends_with('}') {
+		let mut items: Vec<Argument> = Vec::new();
+		for item in split_arguments(&token[1..token.len() - 1])? {
+			items.push(parse_argument(item, interner)?);
+		}
+		return Ok(Argument::List(items));
+	}
+	if let Some(operand) = parse_operand(token, interner)? {
+		return Ok(Argument::Operand(operand));
+	}
+	if is_identifier(token) {
+		return Ok(Argument::Name(interner.intern(token)));
+	}
+	Err(Error::InvalidArgument)
+}
+
+fn parse_operand(token: &str, interner: &mut Interner) -> Result<Option<OperandType>, Error> {
+	if token.starts_with('[') && token.ends_with(']') {
+		return parse_memory(&token[1..token.len() - 1], interner).map(Some);
+	}
+	if let Some(name) = parse_symbol(token) {
+		return Ok(Some(OperandType::Symbol(interner.intern(name))));
+	}
+	if let Some(n) = parse_register(token, 'r') {
+		return Ok(Some(OperandType::IntegerRegister(n)));
+	}
+	if let Some(_n) = parse_register(token, 'f') {
+		#[cfg(feature = "float")]
+		return Ok(Some(OperandType::FloatingRegister(_n)));
+		#[cfg(not(feature = "float"))]
+		return Err(Error::UnsupportedOperand);
+	}
+	if let Some(value) = parse_integer(token) {
+		return Ok(Some(OperandType::IntegerConstant(value)));
+	}
+	if is_floating(token) {
+		#[cfg(feature = "float")]
+		return token.parse::<f64>().map(|value| Some(OperandType::FloatingConstant(value)))
+				.map_err(|_| Error::InvalidArgument);
+		#[cfg(not(feature = "float"))]
+		return Err(Error::UnsupportedOperand);
+	}
+	Ok(None)
+}
+
+/// Memory operand: [address], [base], [base + displacement], [base + index*scale + displacement]
+/// or [@symbol + displacement].
+fn parse_memory(text: &str, interner: &mut Interner) -> Result<OperandType, Error> {
+	let text: String = text.chars().filter(|c| !c.is_whitespace()).collect();
+	// Absolute address is unsigned, it can be above i64::MAX.
+	if let Some(address) = parse_address(&text) {
+		return Ok(OperandType::Memory(address));
+	}
+	// Split into terms, keeping a sign of each one.
+	let mut terms: Vec<(bool, &str)> = Vec::new();
+	let mut negative = false;
+	let mut start: usize = 0;
+	for (i, c) in text.char_indices() {
+		if c == '+' || c == '-' {
+			if i > 0 {
+				terms.push((negative, &text[start..i]));
+			}
+			negative = c == '-';
+			start = i + 1;
+		}
+	}
+	terms.push((negative, &text[start..]));
+
+	let mut base: Option<usize> = None;
+	let mut index: Option<(usize, u8)> = None;
+	let mut displacement: Option<i64> = None;
+	let mut symbol: Option<&str> = None;
+	for (negative, term) in terms {
+		if let Some(name) = parse_symbol(term) {
+			if negative || symbol.is_some() {
+				return Err(Error::InvalidArgument);
+			}
+			symbol = Some(name);
+		} else if let Some(star) = term.find('*') {
+			let register = parse_register(&term[..star], 'r').ok_or(Error::InvalidArgument)?;
+			let scale = term[star + 1..].parse::<u8>().map_err(|_| Error::InvalidArgument)?;
+			if negative || index.is_some() {
+				return Err(Error::InvalidArgument);
+			}
+			index = Some((register, scale));
+		} else if let Some(register) = parse_register(term, 'r') {
+			match (negative, base, index) {
+				(false, None, _)       => base = Some(register),
+				(false, Some(_), None) => index = Some((register, 1)),
+				_ => return Err(Error::InvalidArgument),
+			}
+		} else {
+			let sign = if negative { "-" } else { "" };
+			let value = parse_integer(&format!("{}{}", sign, term)).ok_or(Error::InvalidArgument)?;
+			if displacement.is_some() {
+				return Err(Error::InvalidArgument);
+			}
+			displacement = Some(value);
+		}
+	}
+
+	if let Some(name) = symbol {
+		if base.is_some() || index.is_some() {
+			return Err(Error::InvalidArgument);
+		}
+		return Ok(OperandType::MemorySymbol(interner.intern(name), displacement.unwrap_or(0)));
+	}
+	match (base, index, displacement) {
+		(None, None, Some(address)) if address >= 0 => Ok(OperandType::Memory(address as usize)),
+		(Some(base), None, displacement) => Ok(OperandType::MemoryBase(base, displacement.unwrap_or(0))),
+		(Some(base), Some((index, scale)), displacement) =>
+			Ok(OperandType::MemoryIndex(base, index, scale, displacement.unwrap_or(0))),
+		_ => Err(Error::InvalidArgument),
+	}
+}
+
+fn parse_symbol(token: &str) -> Option<&str> {
+	token.strip_prefix('@').filter(|name| is_identifier(name))
+}
+
+fn parse_register(token: &str, prefix: char) -> Option<usize> {
+	if token.starts_with(prefix) && token.len() > 1 && token[1..].chars().all(|c| c.is_ascii_digit()) {
+		token[1..].parse::<usize>().ok()
+	} else {
+		None
+	}
+}
+
+fn parse_address(token: &str) -> Option<usize> {
+	if token.starts_with("0x") || token.starts_with("0X") {
+		usize::from_str_radix(&token[2..], 16).ok()
+	} else if !token.is_empty() && token.chars().all(|c| c.is_ascii_digit()) {
+		token.parse::<usize>().ok()
+	} else {
+		None
+	}
+}
+
+fn parse_integer(token: &str) -> Option<i64> {
+	let (negative, digits) = match token.strip_prefix('-') {
+		Some(digits) => (true, digits),
+		None         => (false, token),
+	};
+	let hex = digits.starts_with("0x") || digits.starts_with("0X");
+	let magnitude: u64 = if hex {
+		u64::from_str_radix(&digits[2..], 16).ok()?
+	} else if !digits.is_empty() && digits.chars().all(|c| c.is_ascii_digit()) {
+		digits.parse::<u64>().ok()?
+	} else {
+		return None;
+	};
+	match (negative, hex) {
+		// Hex constant is a bit pattern, so 0xFFFFFFFFFFFFFFFF is allowed.
+		(false, true) => Some(magnitude as i64),
+		(false, false) if magnitude <= i64::MAX as u64 => Some(magnitude as i64),
+		(true, _) if magnitude <= 1 << 63 => Some((magnitude as i64).wrapping_neg()),
+		_ => None,
+	}
+}
+
+fn is_floating(token: &str) -> bool {
+	match token {
+		"inf" | "-inf" | "NaN" => true,
+		_ => {
+			let first = token.chars().next().unwrap_or(' ');
+			(first.is_ascii_digit() || first == '-' || first == '.') && token.parse::<f64>().is_ok()
+		},
+	}
+}
+
+fn is_identifier(token: &str) -> bool {
+	let mut chars = token.chars();
+	match chars.next() {
+		Some(c) if c.is_alphabetic() || c == '_' => chars.all(|c| c.is_alphanumeric() || c == '_'),
+		_ => false,
+	}
+}
+
+fn parse_string(token: &str) -> Result<String, Error> {
+	if token.len() < 2 || !token.ends_with('"') {
+		return Err(Error::InvalidArgument);
+	}
+	let mut result = String::new();
+	let mut chars = token[1..token.len() - 1].chars();
+	while let Some(c) = chars.next() {
+		if c != '\\' {
+			result.push(c);
+			continue;
+		}
+		match chars.next() {
+			Some('n')  => result.push('\n'),
+			Some('t')  => result.push('\t'),
+			Some('r')  => result.push('\r'),
+			Some('0')  => result.push('\0'),
+			Some('\\') => result.push('\\'),
+			Some('"')  => result.push('"'),
+			_ => return Err(Error::InvalidArgument),
+		}
+	}
+	Ok(result)
+}
+
+fn format_argument(arg: &Argument) -> String {
+	match *arg {
+		Argument::Operand(operand) => format_operand(operand),
+		Argument::Name(name) => format_name(name),
+		Argument::List(ref items) => {
+			let items: Vec<String> = items.iter().map(format_argument).collect();
+			format!("{{{}}}", items.join(", "))
+		},
+	}
+}
+
+fn format_operand(operand: OperandType) -> String {
+	match operand {
+		OperandType::IntegerRegister(n)  => format!("r{}", n),
+		#[cfg(feature = "float")]
+		OperandType::FloatingRegister(n) => format!("f{}", n),
+		OperandType::Memory(address)     => format!("[{}]", address),
+		OperandType::MemoryBase(base, displacement) =>
+			format!("[r{}{}]", base, format_displacement(displacement)),
+		OperandType::MemoryIndex(base, index, scale, displacement) =>
+			format!("[r{} + r{}*{}{}]", base, index, scale, format_displacement(displacement)),
+		OperandType::Symbol(name)        => format!("@{}", name),
+		OperandType::MemorySymbol(name, displacement) =>
+			format!("[@{}{}]", name, format_displacement(displacement)),
+		OperandType::IntegerConstant(n)  => format!("{}", n),
+		// Debug format always keeps a dot or an exponent, so constant is not confused with integer.
+		#[cfg(feature = "float")]
+		OperandType::FloatingConstant(n) => format!("{:?}", n),
+	}
+}
+
+fn format_displacement(displacement: i64) -> String {
+	match displacement {
+		0 => String::new(),
+		n if n < 0 => format!(" - {}", n.unsigned_abs()),
+		n => format!(" + {}", n),
+	}
+}
+
+fn format_name(name: &str) -> String {
+	let plain = is_identifier(name) && parse_register(name, 'r').is_none()
+			&& parse_register(name, 'f').is_none() && !is_floating(name);
+	if plain {
+		return name.to_owned();
+	}
+	let mut result = String::from("\"");
+	for c in name.chars() {
+		match c {
+			'\n' => result.push_str("\\n"),
+			'\t' => result.push_str("\\t"),
+			'\r' => result.push_str("\\r"),
+			'\0' => result.push_str("\\0"),
+			'\\' => result.push_str("\\\\"),
+			'"'  => result.push_str("\\\""),
+			_    => result.push(c),
+		}
+	}
+	result.push('"');
+	result
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use flags::Condition;
+	use memory::MemoryFormat;
+
+	#[test]
+	fn check_assemble() {
+		let source = "
+			; comment line
+			read_only_data text, u8, \"Hi\"
+			data table, i16be, {1, -2, 0x30}
+			function main
+				move r1, 0xE1EE7     ; trailing comment
+				add r2, 3, -5
+				move [ r1 ], [r2 - 0x10]
+				move [r1+r2*4+8], [r3 + r4]
+				move r5, [ @table - 2 ]
+				add r5, r5, @text
+				jump_zero \"r1\", [16]
+				print_string \"a, b; \\\"c\\\"\\n\"
+				label \"r1\"
+				return
+			end_function
+		";
+		let program = assemble(source).unwrap();
+		assert_eq!(program, vec![
+			Opcode::ReadOnlyData("text", MemoryFormat::from_name("u8").unwrap(), &[72, 105]),
+			Opcode::Data("table", MemoryFormat::from_name("i16be").unwrap(), &[1, -2, 0x30]),
+			Opcode::FunctionStart("main"),
+			Opcode::Move(OperandType::IntegerRegister(1), OperandType::IntegerConstant(0xE1EE7)),
+			Opcode::Add(OperandType::IntegerRegister(2), OperandType::IntegerConstant(3), OperandType::IntegerConstant(-5)),
+			Opcode::Move(OperandType::MemoryBase(1, 0), OperandType::MemoryBase(2, -16)),
+			Opcode::Move(OperandType::MemoryIndex(1, 2, 4, 8), OperandType::MemoryIndex(3, 4, 1, 0)),
+			Opcode::Move(OperandType::IntegerRegister(5), OperandType::MemorySymbol("table", -2)),
+			Opcode::Add(OperandType::IntegerRegister(5), OperandType::IntegerRegister(5), OperandType::Symbol("text")),
+			Opcode::JumpZero("r1", OperandType::Memory(16)),
+			Opcode::PrintString("a, b; \"c\"\n"),
+			Opcode::Label("r1"),
+			Opcode::Return,
+			Opcode::FunctionEnd,
+		]);
+	}
+
+	#[test]
+	fn check_disassemble_roundtrip() {
+		let program = vec![
+			Opcode::Data("empty", MemoryFormat::from_name("i64le").unwrap(), &[]),
+			Opcode::FunctionStart("main"),
+			Opcode::Move(OperandType::IntegerRegister(1), OperandType::IntegerConstant(i64::MIN)),
+			Opcode::Move(OperandType::MemorySymbol("empty", 0), OperandType::Symbol("empty")),
+			Opcode::JumpBelow("f2", OperandType::IntegerRegister(1), OperandType::IntegerConstant(-1)),
+			Opcode::Compare(OperandType::IntegerRegister(1), OperandType::IntegerConstant(3)),
+			Opcode::JumpIf(Condition::BelowUnsigned, "f2"),
+			Opcode::Load(OperandType::IntegerRegister(2), OperandType::IntegerRegister(1), MemoryFormat::from_name("i16be").unwrap()),
+			Opcode::Move(OperandType::MemoryBase(3, i64::MIN), OperandType::MemoryIndex(1, 2, 8, -16)),
+			Opcode::Store(OperandType::MemoryBase(3, 0), OperandType::IntegerConstant(1), MemoryFormat::from_name("u8").unwrap()),
+			Opcode::Realloc(OperandType::IntegerRegister(4), OperandType::IntegerRegister(4), OperandType::IntegerConstant(32)),
+			Opcode::Spawn(OperandType::IntegerRegister(5), "main", &[OperandType::IntegerRegister(1), OperandType::Symbol("empty")]),
+			Opcode::Join(OperandType::IntegerRegister(5)),
+			Opcode::CompareExchange(OperandType::IntegerRegister(1), OperandType::MemoryBase(2, 8),
+				OperandType::IntegerConstant(0), OperandType::IntegerConstant(1)),
+			Opcode::Fence,
+			Opcode::Move(OperandType::Memory(usize::MAX), OperandType::Memory(i64::MAX as usize + 1)),
+			Opcode::TailCall("f1"),
+			Opcode::Switch(OperandType::IntegerRegister(2), &["f1", "r1"], "f2"),
+			Opcode::FuncRef(OperandType::IntegerRegister(1), "main"),
+			Opcode::CallIndirect(OperandType::IntegerRegister(1)),
+			Opcode::Protect("f2", "f2", "f2"),
+			Opcode::Throw(OperandType::IntegerRegister(1)),
+			Opcode::Catch(OperandType::IntegerRegister(2)),
+			Opcode::CoroutineNew(OperandType::IntegerRegister(1), "main", &[]),
+			Opcode::Resume(OperandType::IntegerRegister(2), OperandType::IntegerRegister(3), OperandType::IntegerRegister(1)),
+			Opcode::YieldValue(OperandType::IntegerConstant(-1)),
+			Opcode::TryRecv(OperandType::IntegerRegister(6), OperandType::IntegerRegister(7), OperandType::IntegerRegister(8)),
+			Opcode::PrintString("tab\there"),
+			Opcode::Label("f2"),
+			Opcode::Return,
+			Opcode::FunctionEnd,
+		];
+		let text = disassemble(&program);
+		assert_eq!(assemble(&text).unwrap(), program);
+	}
+
+	#[test]
+	fn check_assemble_fail() {
+		assert_eq!(assemble("function main\n\tmove r1,\n"),
+			Err(Error::AssemblyFailed(2, Box::new(Error::InvalidArgument))));
+		assert_eq!(assemble("\n\n\tjump_far away\n"),
+			Err(Error::AssemblyFailed(3, Box::new(Error::MalformedOpcode))));
+		assert_eq!(assemble("add r1, r2\n"),
+			Err(Error::AssemblyFailed(1, Box::new(Error::MalformedOpcode))));
+		assert_eq!(assemble("move [r1 + r2 + r3], 0\n"),
+			Err(Error::AssemblyFailed(1, Box::new(Error::InvalidArgument))));
+		assert_eq!(assemble("move [-8], 0\n"),
+			Err(Error::AssemblyFailed(1, Box::new(Error::InvalidArgument))));
+		assert_eq!(assemble("jump_if sometimes, end\n"),
+			Err(Error::AssemblyFailed(1, Box::new(Error::InvalidArgument))));
+	}
+}
@@ -0,0 +1,305 @@
+//! Binary form of a program.
+//!
+//! All numbers are little-endian.
+//! Header: magic "PLXB", u16 version.
+//! Name table: u32 count, then every name as u32 length and UTF-8 bytes.
+//! Opcodes: u32 count, then every opcode as u32 index of its mnemonic in the name table,
+//! u8 number of arguments and arguments themselves. Argument is a u8 tag and a payload:
+//! u32 name index or one or more u64 values, depending on the tag.
+//! List is a u32 number of items followed by the items, each one is an argument except a list.
+
+use std::collections::HashMap;
+
+use asm::Interner;
+use error::Error;
+use opcode::{Argument, Opcode};
+use operand::OperandType;
+
+const MAGIC: &[u8] = b"PLXB";
+const VERSION: u16 = 1;
+
+const TAG_INTEGER_REGISTER: u8  = 0;
+#[cfg(feature = "float")]
+const TAG_FLOATING_REGISTER: u8 = 1;
+const TAG_MEMORY: u8            = 2;
+const TAG_INTEGER_CONSTANT: u8  = 3;
+#[cfg(feature = "float")]
+const TAG_FLOATING_CONSTANT: u8 = 4;
+const TAG_NAME: u8              = 5;
+const TAG_MEMORY_BASE: u8       = 6;
+const TAG_MEMORY_INDEX: u8      = 7;
+const TAG_SYMBOL: u8            = 8;
+const TAG_MEMORY_SYMBOL: u8     = 9;
+const TAG_LIST: u8              = 10;
+
+/// Check if data looks like a bytecode (not a text program).
+pub fn is_bytecode(data: &[u8]) -> bool {
+	data.starts_with(MAGIC)
+}
+
+/// Convert a list of opcodes into bytecode.
+pub fn to_bytecode(program: &[Opcode]) -> Vec<u8> {
+	let mut writer = Writer::default();
+	writer.body.extend_from_slice(&(program.len() as u32).to_le_bytes());
+	for opcode in program {
+		let (mnemonic, args) = opcode.to_parts();
+		writer.write_name(mnemonic);
+		writer.body.push(args.len() as u8);
+		for arg in args {
+			writer.write_argument(arg);
+		}
+	}
+
+	let mut result: Vec<u8> = Vec::new();
+	result.extend_from_slice(MAGIC);
+	result.extend_from_slice(&VERSION.to_le_bytes());
+	result.extend_from_slice(&(writer.names.len() as u32).to_le_bytes());
+	for name in writer.names {
+		result.extend_from_slice(&(name.len() as u32).to_le_bytes());
+		result.extend_from_slice(name.as_bytes());
+	}
+	result.extend_from_slice(&writer.body);
+	result
+}
+
+/// Parse bytecode into a list of opcodes.
+pub fn from_bytecode(data: &[u8]) -> Result<Vec<Opcode>, Error> {
+	let mut reader = Reader{data, position: 0};
+	if reader.read_bytes(MAGIC.len())? != MAGIC || reader.read_u16()? != VERSION {
+		return Err(Error::InvalidBytecode);
+	}
+
+	let mut interner = Interner::default();
+	let names_count = reader.read_u32()? as usize;
+	let mut names: Vec<&'static str> = Vec::new();
+	for _ in 0..names_count {
+		let length = reader.read_u32()? as usize;
+		let name = ::std::str::from_utf8(reader.read_bytes(length)?).map_err(|_| Error::InvalidBytecode)?;
+		names.push(interner.intern(name));
+	}
+	let name = |index: u32| -> Result<&'static str, Error> {
+		names.get(index as usize).cloned().ok_or(Error::InvalidBytecode)
+	};
+
+	let opcodes_count = reader.read_u32()? as usize;
+	let mut program: Vec<Opcode> = Vec::new();
+	for _ in 0..opcodes_count {
+		let mnemonic = name(reader.read_u32()?)?;
+		let args_count = reader.read_u8()?;
+		let mut args: Vec<Argument> = Vec::new();
+		for _ in 0..args_count {
+			args.push(read_argument(&mut reader, &name, true)?);
+		}
+		program.push(Opcode::from_parts(mnemonic, &args).map_err(|_| Error::InvalidBytecode)?);
+	}
+
+	if reader.position != data.len() {
+		return Err(Error::InvalidBytecode);
+	}
+	Ok(program)
+}
+
+/// Lists are flat, so nesting of untrusted input can't exhaust the stack.
+fn read_argument<F>(reader: &mut Reader, name: &F, list_allowed: bool) -> Result<Argument, Error>
+		where F: Fn(u32) -> Result<&'static str, Error> {
+	let arg = match reader.read_u8()? {
+		TAG_INTEGER_REGISTER  => Argument::Operand(OperandType::IntegerRegister(reader.read_u64()? as usize)),
+		#[cfg(feature = "float")]
+		TAG_FLOATING_REGISTER => Argument::Operand(OperandType::FloatingRegister(reader.read_u64()? as usize)),
+		TAG_MEMORY            => Argument::Operand(OperandType::Memory(reader.read_u64()? as usize)),
+		TAG_INTEGER_CONSTANT  => Argument::Operand(OperandType::IntegerConstant(reader.read_u64()? as i64)),
+		#[cfg(feature = "float")]
+		TAG_FLOATING_CONSTANT => Argument::Operand(OperandType::FloatingConstant(f64::from_bits(reader.read_u64()?))),
+		TAG_NAME              => Argument::Name(name(reader.read_u32()?)?),
+		TAG_MEMORY_BASE       => Argument::Operand(OperandType::MemoryBase(
+				reader.read_u64()? as usize, reader.read_u64()? as i64)),
+		TAG_MEMORY_INDEX      => Argument::Operand(OperandType::MemoryIndex(reader.read_u64()? as usize,
+				reader.read_u64()? as usize, reader.read_u8()?, reader.read_u64()? as i64)),
+		TAG_SYMBOL            => Argument::Operand(OperandType::Symbol(name(reader.read_u32()?)?)),
+		TAG_MEMORY_SYMBOL     => Argument::Operand(OperandType::MemorySymbol(
+				name(reader.read_u32()?)?, reader.read_u64()? as i64)),
+		TAG_LIST if list_allowed => {
+			let count = reader.read_u32()?;
+			let mut items: Vec<Argument> = Vec::new();
+			for _ in 0..count {
+				items.push(read_argument(reader, name, false)?);
+			}
+			Argument::List(items)
+		},
+		_ => return Err(Error::InvalidBytecode),
+	};
+	Ok(arg)
+}
+
+/// Collects a name table and a body of bytecode.
+#[derive(Default)]
+struct Writer {
+	names: Vec<&'static str>,
+	name_indexes: HashMap<&'static str, u32>,
+	body: Vec<u8>,
+}
+
+impl Writer {
+	fn name_index(&mut self, name: &'static str) -> u32 {
+		let names = &mut self.names;
+		*self.name_indexes.entry(name).or_insert_with(|| {
+			names.push(name);
+			(names.len() - 1) as u32
+		})
+	}
+
+	fn write_argument(&mut self, arg: Argument) {
+		match arg {
+			Argument::Operand(operand) => self.write_operand(operand),
+			Argument::Name(name) => {
+				self.body.push(TAG_NAME);
+				self.write_name(name);
+			},
+			Argument::List(items) => {
+				self.body.push(TAG_LIST);
+				self.body.extend_from_slice(&(items.len() as u32).to_le_bytes());
+				for item in items {
+					self.write_argument(item);
+				}
+			},
+		}
+	}
+
+	fn write_operand(&mut self, operand: OperandType) {
+		match operand {
+			OperandType::IntegerRegister(n) => {
+				self.body.push(TAG_INTEGER_REGISTER);
+				self.write_u64(n as u64);
+			},
+			#[cfg(feature = "float")]
+			OperandType::FloatingRegister(n) => {
+				self.body.push(TAG_FLOATING_REGISTER);
+				self.write_u64(n as u64);
+			},
+			OperandType::Memory(address) => {
+				self.body.push(TAG_MEMORY);
+				self.write_u64(address as u64);
+			},
+			OperandType::MemoryBase(base, displacement) => {
+				self.body.push(TAG_MEMORY_BASE);
+				self.write_u64(base as u64);
+				self.write_u64(displacement as u64);
+			},
+			OperandType::MemoryIndex(base, index, scale, displacement) => {
+				self.body.push(TAG_MEMORY_INDEX);
+				self.write_u64(base as u64);
+				self.write_u64(index as u64);
+				self.body.push(scale);
+				self.write_u64(displacement as u64);
+			},
+			OperandType::IntegerConstant(n) => {
+				self.body.push(TAG_INTEGER_CONSTANT);
+				self.write_u64(n as u64);
+			},
+			#[cfg(feature = "float")]
+			OperandType::FloatingConstant(n) => {
+				self.body.push(TAG_FLOATING_CONSTANT);
+				self.write_u64(n.to_bits());
+			},
+			OperandType::Symbol(name) => {
+				self.body.push(TAG_SYMBOL);
+				self.write_name(name);
+			},
+			OperandType::MemorySymbol(name, displacement) => {
+				self.body.push(TAG_MEMORY_SYMBOL);
+				self.write_name(name);
+				self.write_u64(displacement as u64);
+			},
+		}
+	}
+
+	fn write_name(&mut self, name: &'static str) {
+		let index = self.name_index(name);
+		self.body.extend_from_slice(&index.to_le_bytes());
+	}
+
+	fn write_u64(&mut self, value: u64) {
+		self.body.extend_from_slice(&value.to_le_bytes());
+	}
+}
+
+struct Reader<'d> {
+	data: &'d [u8],
+	position: usize,
+}
+
+impl<'d> Reader<'d> {
+	fn read_bytes(&mut self, count: usize) -> Result<&'d [u8], Error> {
+		let end = self.position.checked_add(count).ok_or(Error::InvalidBytecode)?;
+		let bytes = self.data.get(self.position..end).ok_or(Error::InvalidBytecode)?;
+		self.position = end;
+		Ok(bytes)
+	}
+
+	fn read_u8(&mut self) -> Result<u8, Error> {
+		Ok(self.read_bytes(1)?[0])
+	}
+
+	fn read_u16(&mut self) -> Result<u16, Error> {
+		let mut buffer = [0u8; 2];
+		buffer.copy_from_slice(self.read_bytes(2)?);
+		Ok(u16::from_le_bytes(buffer))
+	}
+
+	fn read_u32(&mut self) -> Result<u32, Error> {
+		let mut buffer = [0u8; 4];
+		buffer.copy_from_slice(self.read_bytes(4)?);
+		Ok(u32::from_le_bytes(buffer))
+	}
+
+	fn read_u64(&mut self) -> Result<u64, Error> {
+		let mut buffer = [0u8; 8];
+		buffer.copy_from_slice(self.read_bytes(8)?);
+		Ok(u64::from_le_bytes(buffer))
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use memory::MemoryFormat;
+
+	#[test]
+	fn check_bytecode_roundtrip() {
+		let program = vec![
+			Opcode::ReadOnlyData("table", MemoryFormat::from_name("u16be").unwrap(), &[1, -2, 0xFFFF]),
+			Opcode::FunctionStart("main"),
+			Opcode::Move(OperandType::IntegerRegister(1), OperandType::IntegerConstant(-7)),
+			Opcode::JumpEqual("end", OperandType::Memory(24), OperandType::IntegerRegister(1)),
+			Opcode::Move(OperandType::MemoryBase(2, -8), OperandType::MemoryIndex(3, 4, 8, 1 << 40)),
+			Opcode::Add(OperandType::MemorySymbol("table", 16), OperandType::Symbol("table"), OperandType::IntegerConstant(1)),
+			Opcode::PrintString("main"),
+			Opcode::Label("end"),
+			Opcode::Return,
+			Opcode::FunctionEnd,
+		];
+		let bytecode = to_bytecode(&program);
+		assert!(is_bytecode(&bytecode));
+		assert_eq!(from_bytecode(&bytecode).unwrap(), program);
+	}
+
+	#[test]
+	fn check_bytecode_fail() {
+		let bytecode = to_bytecode(&[Opcode::FunctionStart("main"), Opcode::Return, Opcode::FunctionEnd]);
+		assert_eq!(from_bytecode(&bytecode[..bytecode.len() - 1]), Err(Error::InvalidBytecode));
+		assert_eq!(from_bytecode(b"PLXB\x02\x00"), Err(Error::InvalidBytecode));
+		assert_eq!(from_bytecode(b"function main"), Err(Error::InvalidBytecode));
+
+		// Spawn with deeply nested list of arguments.
+		let mut bytecode = to_bytecode(&[]);
+		bytecode.truncate(bytecode.len() - 8);
+		bytecode.extend_from_slice(&[1, 0, 0, 0, 5, 0, 0, 0]);
+		bytecode.extend_from_slice(b"spawn");
+		bytecode.extend_from_slice(&[1, 0, 0, 0, 0, 0, 0, 0, 1]);
+		for _ in 0..1_000_000 {
+			bytecode.extend_from_slice(&[TAG_LIST, 1, 0, 0, 0]);
+		}
+		bytecode.extend_from_slice(&[TAG_INTEGER_CONSTANT, 0, 0, 0, 0, 0, 0, 0, 0]);
+		assert_eq!(from_bytecode(&bytecode), Err(Error::InvalidBytecode));
+	}
+}
@@ -0,0 +1,92 @@
+use std::collections::VecDeque;
+
+/// Why a channel operation can't be done right now.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ChannelError {
+	/// Channel is full on send or empty on receive, the task has to wait.
+	WouldBlock,
+	Closed,
+}
+
+/// FIFO queue of integers between tasks.
+#[derive(Debug)]
+pub struct Channel {
+	buffer: VecDeque<i64>,
+	/// Zero means unbounded.
+	capacity: usize,
+	closed: bool,
+}
+
+impl Channel {
+	pub fn new(capacity: usize) -> Channel {
+		Channel {
+			buffer: VecDeque::new(),
+			capacity,
+			closed: false,
+		}
+	}
+
+	/// Sender has to wait. Send into a closed channel fails immediately.
+	pub fn would_block_send(&self) -> bool {
+		!self.closed && self.capacity != 0 && self.buffer.len() >= self.capacity
+	}
+
+	/// Receiver has to wait. Values left in a closed channel can still be received.
+	pub fn would_block_recv(&self) -> bool {
+		!self.closed && self.buffer.is_empty()
+	}
+
+	pub fn try_send(&mut self, value: i64) -> Result<(), ChannelError> {
+		if self.closed {
+			Err(ChannelError::Closed)
+		} else if self.would_block_send() {
+			Err(ChannelError::WouldBlock)
+		} else {
+			self.buffer.push_back(value);
+			Ok(())
+		}
+	}
+
+	pub fn try_recv(&mut self) -> Result<i64, ChannelError> {
+		match self.buffer.pop_front() {
+			Some(value) => Ok(value),
+			None if self.closed => Err(ChannelError::Closed),
+			None => Err(ChannelError::WouldBlock),
+		}
+	}
+
+	pub fn close(&mut self) -> Result<(), ChannelError> {
+		if self.closed {
+			return Err(ChannelError::Closed);
+		}
+		self.closed = true;
+		Ok(())
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn check_channel() {
+		let mut channel = Channel::new(2);
+		assert_eq!(channel.try_recv(), Err(ChannelError::WouldBlock));
+		assert_eq!(channel.try_send(1), Ok(()));
+		assert_eq!(channel.try_send(2), Ok(()));
+		assert!(channel.would_block_send());
+		assert_eq!(channel.try_send(3), Err(ChannelError::WouldBlock));
+		assert_eq!(channel.try_recv(), Ok(1));
+		assert_eq!(channel.close(), Ok(()));
+		assert_eq!(channel.close(), Err(ChannelError::Closed));
+		assert_eq!(channel.try_send(3), Err(ChannelError::Closed));
+		assert_eq!(channel.try_recv(), Ok(2));
+		assert_eq!(channel.try_recv(), Err(ChannelError::Closed));
+
+		let mut unbounded = Channel::new(0);
+		for i in 0..100 {
+			assert_eq!(unbounded.try_send(i), Ok(()));
+		}
+		assert!(!unbounded.would_block_send());
+	}
+}
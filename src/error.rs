@@ -1,3 +1,6 @@
+// failure_derive generates its impls inside a const block.
+#![allow(non_local_definitions)]
+
 /// List of virtual machine errors.
 #[derive(Debug, Fail, Eq, PartialEq)]
 pub enum Error {
@@ -41,7 +44,203 @@ pub enum Error {
 	#[fail(display = "Unsupported operand. Probably you are trying to store int into float or vise versa.")]
 	UnsupportedOperand,
 
+	/// Host failed to perform input or output.
+	#[fail(display = "Input/output operation failed.")]
+	IoFailure,
+
+	/// There is nothing left to read.
+	#[fail(display = "Input is exhausted.")]
+	InputExhausted,
+
+	/// Input can't be parsed as a value of requested type.
+	#[fail(display = "Input is invalid. Only integers can be read.")]
+	InvalidInput,
+
+	/// Two functions with the same name.
+	#[fail(display = "Function {} was already defined before!", _0)]
+	FunctionIsAlreadyDefined(&'static str),
+
+	/// Opcode can't be built from given mnemonic and arguments.
+	#[fail(display = "Unknown opcode or wrong arguments.")]
+	MalformedOpcode,
+
+	/// Argument in a text program can't be parsed.
+	#[fail(display = "Invalid argument. Expected a register, a constant or a name.")]
+	InvalidArgument,
+
+	/// Text program can't be assembled. Contains line number and a reason.
+	#[fail(display = "Line {}: {}", _0, _1)]
+	AssemblyFailed(usize, Box<Error>),
+
+	/// Bytecode is broken or was produced by an incompatible version.
+	#[fail(display = "Bytecode is malformed or has unsupported version.")]
+	InvalidBytecode,
+
+	/// Fuel limit is reached before the program finished.
+	#[fail(display = "Fuel limit of {} opcodes is exhausted.", _0)]
+	FuelExhausted(u64),
+
+	/// Host called a function with wrong number of arguments.
+	#[fail(display = "Function {} expects {} arguments, but {} given.", _0, _1, _2)]
+	WrongNumberOfArguments(&'static str, usize, usize),
+
+	/// Program header is misplaced or repeated.
+	#[fail(display = "Program header must be declared once and before all functions.")]
+	BrokenProgramHeader,
+
+	/// Integer division by zero.
+	#[fail(display = "Division by zero.")]
+	DivisionByZero,
+
+	/// Memory access out of VM memory. Contains address and access width.
+	#[fail(display = "Access of {} bytes at address {} is out of memory.", _1, _0)]
+	MemoryOutOfBounds(usize, usize),
+
+	/// Data block name is used twice.
+	#[fail(display = "Data {} was already defined before!", _0)]
+	SymbolIsAlreadyDefined(&'static str),
+
+	/// Operand refers to a data block that doesn't exist.
+	#[fail(display = "Data {} is not defined!", _0)]
+	SymbolIsNotDefined(&'static str),
+
+	/// Store into read-only data. Contains address.
+	#[fail(display = "Write to read-only memory at address {}.", _0)]
+	ReadOnlyMemory(usize),
+
+	/// Heap has no free block of requested size.
+	#[fail(display = "Out of memory: can't allocate {} bytes.", _0)]
+	OutOfMemory(usize),
+
+	/// Size of a heap block is negative.
+	#[fail(display = "Heap block size {} is negative.", _0)]
+	InvalidHeapSize(i64),
+
+	/// Heap block is freed twice. Contains address.
+	#[fail(display = "Block at address {} is already freed.", _0)]
+	DoubleFree(usize),
+
+	/// Access to a freed heap block. Detected only in debug heap mode.
+	#[fail(display = "Access to freed memory at address {}.", _0)]
+	UseAfterFree(usize),
+
+	/// Address is not a start of a heap block.
+	#[fail(display = "Address {} doesn't point to an allocated block.", _0)]
+	InvalidPointer(usize),
+
+	/// Handle doesn't refer to a task of the current run.
+	#[fail(display = "There is no task with handle {}.", _0)]
+	NoSuchTask(i64),
+
+	/// All tasks are blocked. Contains handles of blocked tasks and functions they are in.
+	#[fail(display = "Deadlock: all tasks are blocked {:?}.", _0)]
+	Deadlock(Vec<(usize, &'static str)>),
+
+	/// Handle doesn't refer to a channel of the current run.
+	#[fail(display = "There is no channel with handle {}.", _0)]
+	NoSuchChannel(i64),
+
+	/// Send into or receive from a closed channel, or close it again. Contains channel handle.
+	#[fail(display = "Channel {} is closed.", _0)]
+	ChannelClosed(usize),
+
+	/// Atomic access to an address that is not aligned to 8 bytes.
+	#[fail(display = "Atomic access at address {} is not aligned to 8 bytes.", _0)]
+	UnalignedAtomic(usize),
+
+	/// Handle doesn't refer to a coroutine of the current run.
+	#[fail(display = "There is no coroutine with handle {}.", _0)]
+	NoSuchCoroutine(i64),
+
+	/// Resume of a coroutine, that is already running or finished. Contains coroutine handle.
+	#[fail(display = "Coroutine {} can't be resumed, it is running or finished.", _0)]
+	CoroutineIsNotSuspended(usize),
+
+	/// YieldValue executed outside of a coroutine.
+	#[fail(display = "Only a coroutine can yield a value.")]
+	YieldOutsideCoroutine,
+
+	/// Exception raised by Throw was not caught. Contains exception code.
+	#[fail(display = "Uncaught exception with code {}.", _0)]
+	UncaughtException(i64),
+
+	/// CallIndirect with a value, that is not a function handle.
+	#[fail(display = "{} is not a valid function handle.", _0)]
+	InvalidFunctionHandle(i64),
+
+	/// TailCall is not directly followed by Return or is in a protected range. Contains function name.
+	#[fail(display = "Tail call in function {} is not in tail position.", _0)]
+	TailCallNotInTailPosition(&'static str),
+
+	/// Call exceeds maximum call depth or stack size. Contains call depth and chain of functions from the entry one.
+	#[fail(display = "Stack overflow at call depth {}: {:?}.", _0, _1)]
+	StackOverflow(usize, Vec<&'static str>),
+
 	/// Not implemented.
 	#[fail(display = "This functionality is not implemented yet. Sorry.")]
 	NotImplemented,
-}
\ No newline at end of file
+}
+
+impl Error {
+	/// Runtime failures caused by data, not by a broken program or VM limits.
+	/// They raise exceptions, that a program can catch.
+	pub fn is_trap(&self) -> bool {
+		matches!(*self,
+			Error::DivisionByZero | Error::MemoryOutOfBounds(..) | Error::ReadOnlyMemory(_) |
+			Error::OutOfMemory(_) | Error::InvalidHeapSize(_) | Error::DoubleFree(_) | Error::UseAfterFree(_) | Error::InvalidPointer(_) |
+			Error::UnalignedAtomic(_) | Error::NoSuchTask(_) | Error::NoSuchChannel(_) | Error::ChannelClosed(_) |
+			Error::NoSuchCoroutine(_) | Error::CoroutineIsNotSuspended(_) | Error::InvalidFunctionHandle(_) |
+			Error::InputExhausted | Error::InvalidInput)
+	}
+
+	/// Process exit code that corresponds to an error.
+	/// 1 and 2 are left for generic failures and wrong command line usage.
+	pub fn exit_code(&self) -> i32 {
+		match *self {
+			Error::NoSuchIntegerRegister(..)      => 10,
+			Error::NoSuchFloatingRegister(..)     => 11,
+			Error::BrokenFunctionDefinition(_)    => 12,
+			Error::FunctionIsNotDefined(_)        => 13,
+			Error::ReturnStackExhausted           => 14,
+			Error::OpcodeMustBeUnreachable        => 15,
+			Error::LabelDoesNotExist(_)           => 16,
+			Error::RestrictedJumpOutOfScope(_)    => 17,
+			Error::UnsupportedOperation           => 18,
+			Error::UnsupportedOperand             => 19,
+			Error::IoFailure                      => 20,
+			Error::InputExhausted                 => 21,
+			Error::InvalidInput                   => 22,
+			Error::FunctionIsAlreadyDefined(_)    => 23,
+			Error::MalformedOpcode                => 24,
+			Error::InvalidArgument                => 25,
+			Error::AssemblyFailed(..)             => 26,
+			Error::InvalidBytecode                => 27,
+			Error::FuelExhausted(_)               => 28,
+			Error::WrongNumberOfArguments(..)     => 29,
+			Error::BrokenProgramHeader            => 30,
+			Error::DivisionByZero                 => 31,
+			Error::MemoryOutOfBounds(..)          => 32,
+			Error::SymbolIsAlreadyDefined(_)      => 33,
+			Error::SymbolIsNotDefined(_)          => 34,
+			Error::ReadOnlyMemory(_)              => 35,
+			Error::OutOfMemory(_)                 => 36,
+			Error::DoubleFree(_)                  => 37,
+			Error::UseAfterFree(_)                => 38,
+			Error::InvalidPointer(_)              => 39,
+			Error::NoSuchTask(_)                  => 40,
+			Error::Deadlock(_)                    => 41,
+			Error::NoSuchChannel(_)               => 42,
+			Error::ChannelClosed(_)               => 43,
+			Error::UnalignedAtomic(_)             => 44,
+			Error::NoSuchCoroutine(_)             => 45,
+			Error::CoroutineIsNotSuspended(_)     => 46,
+			Error::YieldOutsideCoroutine          => 47,
+			Error::UncaughtException(_)           => 48,
+			Error::InvalidFunctionHandle(_)       => 49,
+			Error::TailCallNotInTailPosition(_)   => 50,
+			Error::StackOverflow(..)              => 51,
+			Error::InvalidHeapSize(_)             => 52,
+			Error::NotImplemented                 => 99,
+		}
+	}
+}
@@ -0,0 +1,160 @@
+/// Flags set by integer Compare and arithmetic opcodes, read by JumpIf.
+/// Floating operations never change them.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct Flags {
+	/// Result is zero.
+	pub zero: bool,
+	/// Result is negative.
+	pub sign: bool,
+	/// Unsigned overflow: carry out of addition or borrow out of subtraction.
+	pub carry: bool,
+	/// Signed overflow: result doesn't fit into i64.
+	pub overflow: bool,
+}
+
+impl Flags {
+	/// arg1 + arg2 + carry_in. Result wraps around, carry and overflow flags show it.
+	pub fn add(arg1: i64, arg2: i64, carry_in: bool) -> (i64, Flags) {
+		let (partial, carry1) = (arg1 as u64).overflowing_add(arg2 as u64);
+		let (result, carry2) = partial.overflowing_add(carry_in as u64);
+		let wide: i128 = arg1 as i128 + arg2 as i128 + carry_in as i128;
+		Flags::with_result(result as i64, carry1 || carry2, wide != (result as i64) as i128)
+	}
+
+	/// arg1 - arg2 - borrow_in. Carry flag is set on borrow, i.e. if arg1 < arg2 + borrow_in as unsigned.
+	pub fn sub(arg1: i64, arg2: i64, borrow_in: bool) -> (i64, Flags) {
+		let (partial, borrow1) = (arg1 as u64).overflowing_sub(arg2 as u64);
+		let (result, borrow2) = partial.overflowing_sub(borrow_in as u64);
+		let wide: i128 = arg1 as i128 - arg2 as i128 - borrow_in as i128;
+		Flags::with_result(result as i64, borrow1 || borrow2, wide != (result as i64) as i128)
+	}
+
+	/// arg1 * arg2. Both carry and overflow are set if the full product doesn't fit into i64.
+	pub fn mul(arg1: i64, arg2: i64) -> (i64, Flags) {
+		let wide: i128 = arg1 as i128 * arg2 as i128;
+		let result = wide as i64;
+		let overflow = wide != result as i128;
+		Flags::with_result(result, overflow, overflow)
+	}
+
+	fn with_result(result: i64, carry: bool, overflow: bool) -> (i64, Flags) {
+		(result, Flags {
+			zero: result == 0,
+			sign: result < 0,
+			carry,
+			overflow,
+		})
+	}
+
+	/// Check a condition. Relational conditions mean "arg1 ? arg2" after Compare(arg1, arg2).
+	pub fn check(self, condition: Condition) -> bool {
+		match condition {
+			Condition::Equal              => self.zero,
+			Condition::NotEqual           => !self.zero,
+			Condition::Below              => self.sign != self.overflow,
+			Condition::BelowEqual         => self.zero || self.sign != self.overflow,
+			Condition::Above              => !self.zero && self.sign == self.overflow,
+			Condition::AboveEqual         => self.sign == self.overflow,
+			Condition::BelowUnsigned      => self.carry,
+			Condition::BelowEqualUnsigned => self.carry || self.zero,
+			Condition::AboveUnsigned      => !self.carry && !self.zero,
+			Condition::AboveEqualUnsigned => !self.carry,
+			Condition::Negative           => self.sign,
+			Condition::NotNegative        => !self.sign,
+			Condition::Carry              => self.carry,
+			Condition::NoCarry            => !self.carry,
+			Condition::Overflow           => self.overflow,
+			Condition::NoOverflow         => !self.overflow,
+		}
+	}
+}
+
+/// Condition of JumpIf. Below/Above are signed like in other jumps,
+/// unsigned variants treat values as u64.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Condition {
+	Equal,
+	NotEqual,
+	Below,
+	BelowEqual,
+	Above,
+	AboveEqual,
+	BelowUnsigned,
+	BelowEqualUnsigned,
+	AboveUnsigned,
+	AboveEqualUnsigned,
+	Negative,
+	NotNegative,
+	Carry,
+	NoCarry,
+	Overflow,
+	NoOverflow,
+}
+
+const CONDITION_NAMES: &[(Condition, &str)] = &[
+	(Condition::Equal,              "equal"),
+	(Condition::NotEqual,           "not_equal"),
+	(Condition::Below,              "below"),
+	(Condition::BelowEqual,         "below_equal"),
+	(Condition::Above,              "above"),
+	(Condition::AboveEqual,         "above_equal"),
+	(Condition::BelowUnsigned,      "below_unsigned"),
+	(Condition::BelowEqualUnsigned, "below_equal_unsigned"),
+	(Condition::AboveUnsigned,      "above_unsigned"),
+	(Condition::AboveEqualUnsigned, "above_equal_unsigned"),
+	(Condition::Negative,           "negative"),
+	(Condition::NotNegative,        "not_negative"),
+	(Condition::Carry,              "carry"),
+	(Condition::NoCarry,            "no_carry"),
+	(Condition::Overflow,           "overflow"),
+	(Condition::NoOverflow,         "no_overflow"),
+];
+
+impl Condition {
+	/// Name used in text and bytecode forms.
+	pub fn name(self) -> &'static str {
+		CONDITION_NAMES.iter().find(|&&(c, _)| c == self).map(|&(_, name)| name).unwrap()
+	}
+
+	pub fn from_name(name: &str) -> Option<Condition> {
+		CONDITION_NAMES.iter().find(|&&(_, n)| n == name).map(|&(c, _)| c)
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn check_compare_conditions() {
+		let cases: &[(i64, i64)] = &[(1, 2), (2, 1), (5, 5), (-1, 1), (1, -1), (i64::MIN, i64::MAX), (i64::MAX, i64::MIN),
+			(i64::MIN, 1), (0, i64::MIN)];
+		for &(a, b) in cases {
+			let (_, flags) = Flags::sub(a, b, false);
+			assert_eq!(flags.check(Condition::Equal), a == b);
+			assert_eq!(flags.check(Condition::Below), a < b);
+			assert_eq!(flags.check(Condition::BelowEqual), a <= b);
+			assert_eq!(flags.check(Condition::Above), a > b);
+			assert_eq!(flags.check(Condition::AboveEqual), a >= b);
+			assert_eq!(flags.check(Condition::BelowUnsigned), (a as u64) < (b as u64));
+			assert_eq!(flags.check(Condition::AboveUnsigned), (a as u64) > (b as u64));
+		}
+	}
+
+	#[test]
+	fn check_carry_and_overflow() {
+		assert_eq!(Flags::add(-1, 1, false), (0, Flags{zero: true, sign: false, carry: true, overflow: false}));
+		assert_eq!(Flags::add(i64::MAX, 0, true), (i64::MIN, Flags{zero: false, sign: true, carry: false, overflow: true}));
+		assert_eq!(Flags::sub(0, 0, true), (-1, Flags{zero: false, sign: true, carry: true, overflow: false}));
+		assert_eq!(Flags::mul(i64::MIN, -1), (i64::MIN, Flags{zero: false, sign: true, carry: true, overflow: true}));
+	}
+
+	#[test]
+	fn check_condition_names() {
+		for &(condition, name) in CONDITION_NAMES {
+			assert_eq!(condition.name(), name);
+			assert_eq!(Condition::from_name(name), Some(condition));
+		}
+		assert_eq!(Condition::from_name("zero"), None);
+	}
+}
@@ -1,6 +1,10 @@
 use std::collections::HashMap;
+use std::mem::discriminant; // discriminant will allow to compare enum variants
 use std::ops::Range;
 
+use error::Error;
+use opcode::Opcode;
+
 /// Function is a next abstraction after an Opcode.
 /// The idea is that Function provides some restrictions
 /// and extra safety.
@@ -16,11 +20,106 @@ pub struct Function {
 
 	// A HashMap with labels that are defined inside
 	pub labels: HashMap<&'static str, usize>,
-	// Argument list
-	// Return list
+	/// Number of integer arguments, passed in r0, r1, ...
+	pub arguments: usize,
+	/// Number of integer results, returned in r0, r1, ...
+	pub results: usize,
+	/// Protected opcode ranges and offsets of their handlers, declared by Opcode::Protect.
+	pub handlers: Vec<(Range<usize>, usize)>,
+	/// Jump tables of Opcode::Switch: offset of the opcode -> offsets of its labels and of the default label.
+	pub switch_tables: HashMap<usize, (Vec<usize>, usize)>,
 }
 
 impl Function {
+	/// Define a new function.
+	/// index - index of FunctionStart opcode in a whole program
+	/// program - SLICE of program starting from index!
+	pub fn define(fname: &'static str, index: usize, program: &[Opcode]) -> Result<Function, Error> {
+		let mut function_result: Function = Function{
+			name: fname,
+			opcodes_range: Range{start: (index + 1), end: 0},
+			stackframe_size: 10,
+			labels: HashMap::new(),
+			arguments: 0,
+			results: 0,
+			handlers: Vec::new(),
+			switch_tables: HashMap::new(),
+		};
+		if let Opcode::FunctionStart(_name) = program[0] {
+			let func_end_disc = discriminant(&Opcode::FunctionEnd);
+			let func_end_index_o: Option<usize> = program.iter().position(|&x| discriminant(&x) == func_end_disc);
+
+			if let Some(func_end_index) = func_end_index_o {
+				// Mark where function ends
+				function_result.opcodes_range.end = index + func_end_index;
+
+				// Collect offsets of all labels.
+				for (i, opcode) in program.iter().enumerate().take(func_end_index).skip(1)  {
+					if let Opcode::Label(label_name) = opcode {
+						// Label offset = global offset (index) + local offset (i)
+						function_result.labels.insert(label_name, index + i);
+						continue;
+					}
+					if let Opcode::FunctionStart(func_name) = opcode {
+						// Did you try to define a nested function?
+						return Err(Error::BrokenFunctionDefinition(func_name));
+					}
+					if let Opcode::Registers(..) | Opcode::Data(..) | Opcode::ReadOnlyData(..) = opcode {
+						// Program header and data inside a function.
+						return Err(Error::BrokenFunctionDefinition(fname));
+					}
+					if let Opcode::Signature(arguments, results) = *opcode {
+						// Signature is allowed only right after FunctionStart.
+						if i != 1 {
+							return Err(Error::BrokenFunctionDefinition(fname));
+						}
+						function_result.arguments = arguments;
+						function_result.results = results;
+					}
+				}
+
+				// Protected ranges and jump tables may refer to labels defined after them.
+				let labels = &function_result.labels;
+				let offset = |name: &'static str| labels.get(name).cloned().ok_or(Error::LabelDoesNotExist(name));
+				for (i, opcode) in program.iter().enumerate().take(func_end_index) {
+					if let Opcode::Protect(start, end, handler) = *opcode {
+						let range: Range<usize> = offset(start)?..offset(end)?;
+						if range.start > range.end {
+							return Err(Error::BrokenFunctionDefinition(fname));
+						}
+						let handler = offset(handler)?;
+						function_result.handlers.push((range, handler));
+					}
+					if let Opcode::Switch(_index, labels, default) = *opcode {
+						let table = labels.iter().map(|&name| offset(name)).collect::<Result<Vec<usize>, Error>>()?;
+						let default = offset(default)?;
+						function_result.switch_tables.insert(index + i, (table, default));
+					}
+				}
+			} else {
+				return Err(Error::BrokenFunctionDefinition(fname));
+			}
+		} else {
+			return Err(Error::BrokenFunctionDefinition(fname));
+		}
+
+		Ok(function_result)
+	}
+
+	/// Handler of the innermost protected range, that contains an opcode.
+	pub fn find_handler(&self, opcode_offset: usize) -> Option<usize> {
+		self.handlers.iter()
+				.filter(|&(range, _)| range.start <= opcode_offset && opcode_offset < range.end)
+				.min_by_key(|&(range, _)| range.end - range.start)
+				.map(|&(_, handler)| handler)
+	}
+
+	/// Nothing is left to do in the frame after a tail call at a given offset: it is followed by Return
+	/// and no handler of the frame can catch exceptions of the callee.
+	pub fn is_tail_position(&self, opcode_offset: usize, opcodes: &[Opcode]) -> bool {
+		opcodes.get(opcode_offset + 1) == Some(&Opcode::Return) && self.find_handler(opcode_offset).is_none()
+	}
+
 	pub fn is_opcode_in_range(&self, opcode_offset: usize) -> bool {
 		// strict '>' because END in range should point to return with no exceptions.
 		// TODO: Use #![feature(range_contains)] when it is stable
@@ -0,0 +1,164 @@
+use std::collections::BTreeMap;
+
+use error::Error;
+use memory::Memory;
+
+/// Heap blocks are aligned to this number of bytes.
+const HEAP_ALIGNMENT: usize = 8;
+
+/// First-fit allocator of the memory region after program data.
+/// Bookkeeping is kept outside of VM memory, so a program can't corrupt it.
+/// Allocated blocks are zeroed. Address 0 is never returned, so it can be used as a null pointer.
+#[derive(Debug)]
+pub struct Heap {
+	start: usize,
+	end: usize,
+	/// Live blocks: address -> size.
+	blocks: BTreeMap<usize, usize>,
+	/// Freed blocks, that were not reused yet: address -> size.
+	freed: BTreeMap<usize, usize>,
+	/// Report access to freed blocks.
+	debug: bool,
+}
+
+impl Heap {
+	/// Heap occupies [start, end) rounded to alignment.
+	pub fn new(start: usize, end: usize, debug: bool) -> Heap {
+		Heap {
+			start: align(start).max(HEAP_ALIGNMENT),
+			end,
+			blocks: BTreeMap::new(),
+			freed: BTreeMap::new(),
+			debug,
+		}
+	}
+
+	/// Allocate a zeroed block and return its address.
+	pub fn alloc(&mut self, memory: &mut Memory, size: usize) -> Result<usize, Error> {
+		let aligned_size = align(size.max(1));
+		let mut candidate = self.start;
+		for (&address, &block_size) in &self.blocks {
+			if address >= candidate && address - candidate >= aligned_size {
+				break;
+			}
+			candidate = address + block_size;
+		}
+		if candidate > self.end || self.end - candidate < aligned_size {
+			return Err(Error::OutOfMemory(size));
+		}
+		self.blocks.insert(candidate, aligned_size);
+		self.forget_freed(candidate, aligned_size);
+		zero(memory, candidate, aligned_size);
+		Ok(candidate)
+	}
+
+	/// Release a block returned by alloc or realloc.
+	pub fn free(&mut self, address: usize) -> Result<(), Error> {
+		match self.blocks.remove(&address) {
+			Some(size) => {
+				self.freed.insert(address, size);
+				Ok(())
+			},
+			None if self.freed.contains_key(&address) => Err(Error::DoubleFree(address)),
+			None => Err(Error::InvalidPointer(address)),
+		}
+	}
+
+	/// Resize a block keeping its content. Block is moved if it can't grow in place.
+	pub fn realloc(&mut self, memory: &mut Memory, address: usize, size: usize) -> Result<usize, Error> {
+		let old_size: usize = match self.blocks.get(&address) {
+			Some(&old_size) => old_size,
+			None if self.freed.contains_key(&address) => return Err(Error::UseAfterFree(address)),
+			None => return Err(Error::InvalidPointer(address)),
+		};
+		let aligned_size = align(size.max(1));
+		let next_block: usize = self.blocks.range(address + 1..).next().map_or(self.end, |(&next, _)| next);
+		if next_block - address >= aligned_size {
+			self.blocks.insert(address, aligned_size);
+			if aligned_size > old_size {
+				self.forget_freed(address, aligned_size);
+				zero(memory, address + old_size, aligned_size - old_size);
+			}
+			return Ok(address);
+		}
+		let new_address = self.alloc(memory, size)?;
+		memory.bytes_mut().copy_within(address..address + old_size, new_address);
+		self.free(address)?;
+		Ok(new_address)
+	}
+
+	/// In debug mode fail on access to a freed block.
+	pub fn check_access(&self, address: usize, width: usize) -> Result<(), Error> {
+		if !self.debug {
+			return Ok(());
+		}
+		// Freed blocks don't overlap, so only the last one starting before access end matters.
+		let end = address.saturating_add(width);
+		match self.freed.range(..end).next_back() {
+			Some((&start, &size)) if start + size > address => Err(Error::UseAfterFree(address)),
+			_ => Ok(()),
+		}
+	}
+
+	/// Reused memory is not freed anymore.
+	fn forget_freed(&mut self, address: usize, size: usize) {
+		let reused: Vec<usize> = self.freed.range(..address + size)
+				.filter(|&(&start, &freed_size)| start + freed_size > address)
+				.map(|(&start, _)| start)
+				.collect();
+		for start in reused {
+			self.freed.remove(&start);
+		}
+	}
+}
+
+fn align(size: usize) -> usize {
+	size.div_ceil(HEAP_ALIGNMENT) * HEAP_ALIGNMENT
+}
+
+fn zero(memory: &mut Memory, address: usize, size: usize) {
+	for byte in &mut memory.bytes_mut()[address..address + size] {
+		*byte = 0;
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn check_alloc_free() {
+		let mut memory = Memory::new(64);
+		let mut heap = Heap::new(3, 64, true);
+		let a = heap.alloc(&mut memory, 10).unwrap();
+		let b = heap.alloc(&mut memory, 8).unwrap();
+		assert_eq!((a, b), (8, 24));
+		assert_eq!(heap.alloc(&mut memory, 40), Err(Error::OutOfMemory(40)));
+		heap.free(a).unwrap();
+		assert_eq!(heap.check_access(20, 8), Err(Error::UseAfterFree(20)));
+		assert_eq!(heap.check_access(24, 8), Ok(()));
+		assert_eq!(heap.free(a), Err(Error::DoubleFree(a)));
+		assert_eq!(heap.free(12), Err(Error::InvalidPointer(12)));
+		// First fit reuses the freed block.
+		assert_eq!(heap.alloc(&mut memory, 16), Ok(8));
+		assert_eq!(heap.check_access(8, 8), Ok(()));
+	}
+
+	#[test]
+	fn check_realloc() {
+		let mut memory = Memory::new(64);
+		let mut heap = Heap::new(0, 64, false);
+		let a = heap.alloc(&mut memory, 8).unwrap();
+		let b = heap.alloc(&mut memory, 8).unwrap();
+		memory.bytes_mut()[a] = 42;
+		// Can't grow in place, moved after b.
+		let c = heap.realloc(&mut memory, a, 16).unwrap();
+		assert_eq!(c, 24);
+		assert_eq!(&memory.bytes()[c..c + 16], &[42, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0]);
+		// The last block grows in place.
+		assert_eq!(heap.realloc(&mut memory, c, 40), Ok(c));
+		assert_eq!(heap.realloc(&mut memory, c, 41), Err(Error::OutOfMemory(41)));
+		assert_eq!(heap.realloc(&mut memory, a, 8), Err(Error::UseAfterFree(a)));
+		assert_eq!(heap.free(b), Ok(()));
+	}
+}
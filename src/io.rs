@@ -0,0 +1,90 @@
+use std::collections::VecDeque;
+use std::fmt::Debug;
+use std::io::{self, BufRead, Write};
+use std::sync::{Arc, Mutex};
+
+use error::Error;
+
+/// System interface of a virtual machine.
+/// All input/output opcodes are routed through it, so the host decides
+/// where the data actually goes.
+pub trait Io: Debug + Send {
+	/// Print an integer value. No separators are added.
+	fn print_integer(&mut self, value: i64) -> Result<(), Error>;
+	/// Print a string as is.
+	fn print_string(&mut self, value: &str) -> Result<(), Error>;
+	/// Read a single integer value.
+	fn read_integer(&mut self) -> Result<i64, Error>;
+}
+
+/// Default implementation, that uses process stdin and stdout.
+/// Integers are read one per line.
+#[derive(Debug, Default)]
+pub struct StdIo;
+
+impl Io for StdIo {
+	fn print_integer(&mut self, value: i64) -> Result<(), Error> {
+		write!(io::stdout(), "{}", value).map_err(|_| Error::IoFailure)
+	}
+
+	fn print_string(&mut self, value: &str) -> Result<(), Error> {
+		write!(io::stdout(), "{}", value).map_err(|_| Error::IoFailure)
+	}
+
+	fn read_integer(&mut self) -> Result<i64, Error> {
+		// Flush prompt, if any, before blocking on input.
+		io::stdout().flush().map_err(|_| Error::IoFailure)?;
+		let mut line = String::new();
+		let stdin = io::stdin();
+		let read_bytes = stdin.lock().read_line(&mut line).map_err(|_| Error::IoFailure)?;
+		if read_bytes == 0 {
+			return Err(Error::InputExhausted);
+		}
+		line.trim().parse::<i64>().map_err(|_| Error::InvalidInput)
+	}
+}
+
+#[derive(Debug, Default)]
+struct MemoryIoBuffers {
+	input: VecDeque<i64>,
+	output: String,
+}
+
+/// In-memory implementation. Input is a predefined list of integers,
+/// output is collected into a string.
+/// Clones share the same buffers, so keep a clone to inspect output after a run.
+#[derive(Debug, Default, Clone)]
+pub struct MemoryIo {
+	buffers: Arc<Mutex<MemoryIoBuffers>>,
+}
+
+impl MemoryIo {
+	pub fn new(input: &[i64]) -> MemoryIo {
+		let buffers = MemoryIoBuffers {
+			input: input.iter().cloned().collect(),
+			output: String::new(),
+		};
+		MemoryIo{buffers: Arc::new(Mutex::new(buffers))}
+	}
+
+	/// Everything printed so far.
+	pub fn output(&self) -> String {
+		self.buffers.lock().unwrap().output.clone()
+	}
+}
+
+impl Io for MemoryIo {
+	fn print_integer(&mut self, value: i64) -> Result<(), Error> {
+		self.buffers.lock().map_err(|_| Error::IoFailure)?.output.push_str(&value.to_string());
+		Ok(())
+	}
+
+	fn print_string(&mut self, value: &str) -> Result<(), Error> {
+		self.buffers.lock().map_err(|_| Error::IoFailure)?.output.push_str(value);
+		Ok(())
+	}
+
+	fn read_integer(&mut self) -> Result<i64, Error> {
+		self.buffers.lock().map_err(|_| Error::IoFailure)?.input.pop_front().ok_or(Error::InputExhausted)
+	}
+}
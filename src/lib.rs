@@ -0,0 +1,34 @@
+//! Parallax is a register-based virtual machine.
+//! This crate provides the machine itself, its opcodes and operands,
+//! so it can be embedded into other applications.
+
+#[macro_use]
+extern crate failure;
+
+mod error;
+mod flags;
+mod operand;
+mod opcode;
+mod function;
+mod memory;
+mod heap;
+mod channel;
+mod io;
+mod program;
+mod asm;
+mod bytecode;
+mod parallax_vm;
+mod parallel;
+
+pub use asm::{assemble, disassemble};
+pub use bytecode::{from_bytecode, is_bytecode, to_bytecode};
+pub use error::Error;
+pub use flags::{Condition, Flags};
+pub use function::Function;
+pub use io::{Io, StdIo, MemoryIo};
+pub use memory::MemoryFormat;
+pub use opcode::{Argument, Opcode};
+pub use operand::OperandType;
+pub use parallax_vm::{ParallaxVm, RunOptions, VmConfig};
+pub use parallel::{run_parallel, Job, JobResult};
+pub use program::{DataBlock, Program};
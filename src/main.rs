@@ -1,37 +1,180 @@
-#[macro_use]
-extern crate failure;
-
-mod error;
-mod operand;
-mod opcode;
-mod function;
-mod parallax_vm;
-
-pub use error::Error;
-pub use opcode::Opcode;
-pub use operand::OperandType;
-pub use parallax_vm::ParallaxVm;
-
-fn main() -> Result<(), Error> {
-	let mut vm = ParallaxVm::default();
-	let application: Vec<Opcode> = vec![
-/*01*/		Opcode::FunctionStart("main"),
-/*02*/		Opcode::Move(OperandType::IntegerRegister(1), OperandType::IntegerConstant(0xE1EE7)),
-/*03*/		Opcode::Add(OperandType::IntegerRegister(2), OperandType::IntegerConstant(3), OperandType::IntegerConstant(5)),
-/*04*/		Opcode::Jump("skip_next_opcode"),
-/*05*/		Opcode::Add(OperandType::IntegerRegister(3), OperandType::IntegerRegister(2), OperandType::IntegerConstant(-1)),
-/*06*/		Opcode::Label("skip_next_opcode"),
-/*07*/		Opcode::Call("test1"),
-/*08*/		Opcode::Sub(OperandType::IntegerRegister(2), OperandType::IntegerRegister(2), OperandType::IntegerConstant(6)),
-/*09*/		Opcode::Return,
-/*10*/		Opcode::FunctionEnd,
-/*11*/		Opcode::FunctionStart("test1"),
-/*12*/		Opcode::Add(OperandType::IntegerRegister(10), OperandType::IntegerConstant(31), OperandType::IntegerConstant(5)),
-/*13*/		Opcode::Return,
-/*14*/		Opcode::FunctionEnd,
-	];
-	let res = ParallaxVm::run(&mut vm, application.as_slice())?;
-	println!("{:?}", vm);
-	println!("Execution time: {} seconds.", res);
+extern crate parallax;
+
+use std::env;
+use std::fs;
+use std::process;
+use std::time::Instant;
+
+use parallax::{Error, Opcode, ParallaxVm, Program, StdIo, VmConfig};
+use parallax::{assemble, disassemble, from_bytecode, is_bytecode, to_bytecode};
+
+const USAGE: &str = "Usage:
+	parallax run <program> [--entry <function>] [--arg <integer>]... [--fuel <opcodes>] [--dump-registers]
+	parallax check <program>
+	parallax asm <program.pasm> <program.pbc>
+	parallax disasm <program.pbc> [<program.pasm>]
+
+Program can be either a text (.pasm) or a bytecode (.pbc) file.
+Arguments are passed to the entry function, its results are printed one per line.";
+
+/// Failures of the command line tool itself.
+enum CliError {
+	/// Wrong arguments, exit code 2.
+	Usage(String),
+	/// Can't read or write a file, exit code 1.
+	File(String),
+	/// Error reported by the VM, exit code depends on the error.
+	Vm(Error),
+}
+
+impl From<Error> for CliError {
+	fn from(error: Error) -> CliError {
+		CliError::Vm(error)
+	}
+}
+
+struct RunOptions {
+	entry: &'static str,
+	args: Vec<i64>,
+	fuel: Option<u64>,
+	dump_registers: bool,
+}
+
+fn main() {
+	let args: Vec<String> = env::args().skip(1).collect();
+	match execute(&args) {
+		Ok(()) => {},
+		Err(CliError::Usage(message)) => {
+			eprintln!("{}\n\n{}", message, USAGE);
+			process::exit(2);
+		},
+		Err(CliError::File(message)) => {
+			eprintln!("{}", message);
+			process::exit(1);
+		},
+		Err(CliError::Vm(error)) => {
+			eprintln!("Error: {}", error);
+			process::exit(error.exit_code());
+		},
+	}
+}
+
+fn execute(args: &[String]) -> Result<(), CliError> {
+	let command = args.first().ok_or_else(|| CliError::Usage("No command given.".to_owned()))?;
+	match (command.as_str(), &args[1..]) {
+		("run", [path, options @ ..]) => run(path, parse_run_options(options)?),
+		("check", [path]) => {
+			let program = Program::new(&load(path)?)?;
+			create_vm(&program).verify(&program)?;
+			println!("{}: OK", path);
+			Ok(())
+		},
+		("asm", [input, output]) => {
+			let bytecode = to_bytecode(&load(input)?);
+			fs::write(output, bytecode).map_err(|e| CliError::File(format!("Can't write {}: {}", output, e)))
+		},
+		("disasm", [input]) => {
+			print!("{}", disassemble(&load(input)?));
+			Ok(())
+		},
+		("disasm", [input, output]) => {
+			let text = disassemble(&load(input)?);
+			fs::write(output, text).map_err(|e| CliError::File(format!("Can't write {}: {}", output, e)))
+		},
+		_ => Err(CliError::Usage(format!("Wrong arguments for \"{}\" command.", command))),
+	}
+}
+
+fn parse_run_options(args: &[String]) -> Result<RunOptions, CliError> {
+	let mut options = RunOptions {
+		entry: "main",
+		args: Vec::new(),
+		fuel: None,
+		dump_registers: false,
+	};
+	let mut args = args.iter();
+	while let Some(arg) = args.next() {
+		match arg.as_str() {
+			"--entry" => {
+				let name = args.next().ok_or_else(|| CliError::Usage("--entry requires a function name.".to_owned()))?;
+				// Function names live as long as the program.
+				options.entry = Box::leak(name.clone().into_boxed_str());
+			},
+			"--arg" => {
+				let value = args.next()
+						.and_then(|value| value.parse::<i64>().ok())
+						.ok_or_else(|| CliError::Usage("--arg requires an integer value.".to_owned()))?;
+				options.args.push(value);
+			},
+			"--fuel" => {
+				let fuel = args.next()
+						.and_then(|value| value.parse::<u64>().ok())
+						.ok_or_else(|| CliError::Usage("--fuel requires a number of opcodes.".to_owned()))?;
+				options.fuel = Some(fuel);
+			},
+			"--dump-registers" => options.dump_registers = true,
+			_ => return Err(CliError::Usage(format!("Unknown option \"{}\".", arg))),
+		}
+	}
+	Ok(options)
+}
+
+/// Read a program either in text or in bytecode form.
+fn load(path: &str) -> Result<Vec<Opcode>, CliError> {
+	let data = fs::read(path).map_err(|e| CliError::File(format!("Can't read {}: {}", path, e)))?;
+	if is_bytecode(&data) {
+		Ok(from_bytecode(&data)?)
+	} else {
+		let text = String::from_utf8(data).map_err(|_| CliError::File(format!("{} is not a valid UTF-8 text.", path)))?;
+		Ok(assemble(&text)?)
+	}
+}
+
+/// VM with as many registers as the program declares in its header.
+fn create_vm(program: &Program) -> ParallaxVm {
+	let mut config = VmConfig::default();
+	if let Some((integer, floating)) = program.registers() {
+		config.integer_registers = integer;
+		config.floating_registers = floating;
+	}
+	ParallaxVm::new(config, Box::new(StdIo))
+}
+
+fn run(path: &str, options: RunOptions) -> Result<(), CliError> {
+	let program = Program::new(&load(path)?)?;
+	let mut vm = create_vm(&program);
+	vm.verify(&program)?;
+	vm.set_fuel_limit(options.fuel);
+	let start_time = Instant::now();
+	let results = vm.run_function(&program, options.entry, &options.args)?;
+	let elapsed = start_time.elapsed().as_secs();
+	for result in results {
+		println!("{}", result);
+	}
+	if options.dump_registers {
+		println!("{}", registers_json(&vm));
+	}
+	eprintln!("Execution time: {} seconds.", elapsed);
 	Ok(())
-}
\ No newline at end of file
+}
+
+fn registers_json(vm: &ParallaxVm) -> String {
+	let integers: Vec<String> = vm.get_integer_registers().iter().map(|x| x.to_string()).collect();
+	let mut fields: Vec<String> = vec![format!("\"integer_registers\": [{}]", integers.join(", "))];
+	fields.extend(floating_registers_json(vm));
+	format!("{{{}}}", fields.join(", "))
+}
+
+#[cfg(feature = "float")]
+fn floating_registers_json(vm: &ParallaxVm) -> Option<String> {
+	// JSON has no NaN and infinities.
+	let floats: Vec<String> = vm.get_floating_registers().iter()
+			.map(|x| if x.is_finite() { format!("{:?}", x) } else { "null".to_owned() })
+			.collect();
+	Some(format!("\"floating_registers\": [{}]", floats.join(", ")))
+}
+
+#[cfg(not(feature = "float"))]
+fn floating_registers_json(_vm: &ParallaxVm) -> Option<String> {
+	None
+}
@@ -0,0 +1,161 @@
+use std::ops::Range;
+
+use error::Error;
+
+/// How a value is laid out in memory: width in bytes, extension and byte order.
+/// Signedness matters only for loads of values narrower than 64 bits.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MemoryFormat {
+	/// 1, 2, 4 or 8 bytes.
+	width: usize,
+	/// Sign-extend on load, zero-extend otherwise.
+	signed: bool,
+	/// Most significant byte first.
+	big_endian: bool,
+}
+
+/// Format names used in text and bytecode forms.
+/// Single byte has no byte order, 64-bit value doesn't need extension.
+const FORMAT_NAMES: &[(MemoryFormat, &str)] = &[
+	(MemoryFormat::new(1, false, false), "u8"),
+	(MemoryFormat::new(1, true,  false), "i8"),
+	(MemoryFormat::new(2, false, false), "u16le"),
+	(MemoryFormat::new(2, false, true),  "u16be"),
+	(MemoryFormat::new(2, true,  false), "i16le"),
+	(MemoryFormat::new(2, true,  true),  "i16be"),
+	(MemoryFormat::new(4, false, false), "u32le"),
+	(MemoryFormat::new(4, false, true),  "u32be"),
+	(MemoryFormat::new(4, true,  false), "i32le"),
+	(MemoryFormat::new(4, true,  true),  "i32be"),
+	(MemoryFormat::new(8, true,  false), "i64le"),
+	(MemoryFormat::new(8, true,  true),  "i64be"),
+];
+
+impl MemoryFormat {
+	/// Format of Memory operands: 64-bit little-endian.
+	pub const WORD: MemoryFormat = MemoryFormat::new(8, true, false);
+
+	const fn new(width: usize, signed: bool, big_endian: bool) -> MemoryFormat {
+		MemoryFormat{width, signed, big_endian}
+	}
+
+	/// Size of a value in bytes.
+	pub fn width(self) -> usize {
+		self.width
+	}
+
+	pub fn name(self) -> &'static str {
+		FORMAT_NAMES.iter().find(|&&(f, _)| f == self).map(|&(_, name)| name).unwrap()
+	}
+
+	pub fn from_name(name: &str) -> Option<MemoryFormat> {
+		FORMAT_NAMES.iter().find(|&&(_, n)| n == name).map(|&(f, _)| f)
+	}
+}
+
+/// Linear byte-addressed memory of a VM. Initially filled with zeros.
+#[derive(Debug)]
+pub struct Memory {
+	bytes: Vec<u8>,
+	/// Stores into these ranges fail.
+	read_only: Vec<Range<usize>>,
+}
+
+impl Memory {
+	pub fn new(size: usize) -> Memory {
+		Memory{bytes: vec![0; size], read_only: Vec::new()}
+	}
+
+	/// Copy data into memory regardless of read-only ranges.
+	pub fn write_bytes(&mut self, address: usize, data: &[u8]) -> Result<(), Error> {
+		self.range_mut(address, data.len())?.copy_from_slice(data);
+		Ok(())
+	}
+
+	/// Replace all read-only ranges.
+	pub fn set_read_only(&mut self, ranges: Vec<Range<usize>>) {
+		self.read_only = ranges;
+	}
+
+	pub fn bytes(&self) -> &[u8] {
+		&self.bytes
+	}
+
+	pub fn bytes_mut(&mut self) -> &mut [u8] {
+		&mut self.bytes
+	}
+
+	pub fn clear(&mut self) {
+		for byte in self.bytes.iter_mut() {
+			*byte = 0;
+		}
+	}
+
+	/// Load a value and extend it to i64.
+	pub fn load(&self, address: usize, format: MemoryFormat) -> Result<i64, Error> {
+		let bytes = self.range(address, format.width)?;
+		let mut value: u64 = 0;
+		for i in 0..format.width {
+			let byte = if format.big_endian { bytes[i] } else { bytes[format.width - 1 - i] };
+			value = (value << 8) | byte as u64;
+		}
+		let unused_bits = 64 - 8 * format.width as u32;
+		if format.signed {
+			Ok(((value << unused_bits) as i64) >> unused_bits)
+		} else {
+			Ok(value as i64)
+		}
+	}
+
+	/// Store the lowest bytes of a value, the rest is truncated.
+	pub fn store(&mut self, address: usize, format: MemoryFormat, value: i64) -> Result<(), Error> {
+		let width = format.width;
+		// Empty ranges protect nothing, even though their start may be inside of the stored bytes.
+		if self.read_only.iter().any(|range| !range.is_empty() && address < range.end && range.start < address.saturating_add(width)) {
+			return Err(Error::ReadOnlyMemory(address));
+		}
+		let bytes = self.range_mut(address, width)?;
+		for (i, byte) in bytes.iter_mut().enumerate() {
+			let shift = if format.big_endian { 8 * (width - 1 - i) } else { 8 * i };
+			*byte = (value >> shift) as u8;
+		}
+		Ok(())
+	}
+
+	fn range(&self, address: usize, width: usize) -> Result<&[u8], Error> {
+		let end = address.checked_add(width).ok_or(Error::MemoryOutOfBounds(address, width))?;
+		self.bytes.get(address..end).ok_or(Error::MemoryOutOfBounds(address, width))
+	}
+
+	fn range_mut(&mut self, address: usize, width: usize) -> Result<&mut [u8], Error> {
+		let end = address.checked_add(width).ok_or(Error::MemoryOutOfBounds(address, width))?;
+		self.bytes.get_mut(address..end).ok_or(Error::MemoryOutOfBounds(address, width))
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn check_load_store() {
+		let mut memory = Memory::new(16);
+		memory.store(0, MemoryFormat::from_name("u32be").unwrap(), 0x1234_80FF).unwrap();
+		assert_eq!(memory.load(0, MemoryFormat::from_name("u16be").unwrap()), Ok(0x1234));
+		assert_eq!(memory.load(2, MemoryFormat::from_name("u16le").unwrap()), Ok(0xFF80));
+		assert_eq!(memory.load(2, MemoryFormat::from_name("i16le").unwrap()), Ok(-128));
+		assert_eq!(memory.load(2, MemoryFormat::from_name("i8").unwrap()), Ok(-128));
+		assert_eq!(memory.load(3, MemoryFormat::from_name("u8").unwrap()), Ok(0xFF));
+		memory.store(8, MemoryFormat::WORD, -2).unwrap();
+		assert_eq!(memory.load(8, MemoryFormat::from_name("u32le").unwrap()), Ok(0xFFFF_FFFE));
+		assert_eq!(memory.load(8, MemoryFormat::from_name("i64be").unwrap()), Ok(-0x0100_0000_0000_0001));
+		assert_eq!(memory.load(9, MemoryFormat::WORD), Err(Error::MemoryOutOfBounds(9, 8)));
+		assert_eq!(memory.store(usize::MAX, MemoryFormat::WORD, 0), Err(Error::MemoryOutOfBounds(usize::MAX, 8)));
+
+		memory.set_read_only(vec![4..6, 12..12]);
+		assert_eq!(memory.store(5, MemoryFormat::from_name("u8").unwrap(), 0), Err(Error::ReadOnlyMemory(5)));
+		assert_eq!(memory.store(0, MemoryFormat::WORD, 0), Err(Error::ReadOnlyMemory(0)));
+		assert_eq!(memory.store(6, MemoryFormat::from_name("u16le").unwrap(), 0), Ok(()));
+		assert_eq!(memory.store(8, MemoryFormat::WORD, 0), Ok(()));
+	}
+}
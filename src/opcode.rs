@@ -1,15 +1,39 @@
+use error::Error;
+use flags::Condition;
+use memory::MemoryFormat;
 use operand::OperandType;
 
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, PartialEq)]
 /// Operation code.
 pub enum Opcode {
+//=========================== PROGRAM HEADER ==================================
+	/// Number of integer and floating registers required by a program.
+	/// Optional, but if present must precede all functions
+	Registers(usize, usize),
+//============================= DATA ==========================================
+	/// Named block of initialized data: elements of given format.
+	/// Must be outside of functions. Blocks are placed at the beginning of VM memory
+	/// before a run, operands refer to them by name, see OperandType::Symbol
+	Data(&'static str, MemoryFormat, &'static [i64]),
+	/// Same as Data, but writes into the block fail
+	ReadOnlyData(&'static str, MemoryFormat, &'static [i64]),
 //======================== FUNCTION-RELATED ===================================
 	/// Special marker that defines function start
 	FunctionStart(&'static str),
 	/// Function end.
 	FunctionEnd,
+	/// Declares number of integer arguments and results of a function.
+	/// Optional, but if present must directly follow FunctionStart
+	Signature(usize, usize),
 	/// Call function by it's name
 	Call(&'static str),
+	/// Call function replacing the current frame, so it returns directly to the caller.
+	/// It must be followed by Return and be outside of protected ranges, checked by verifier and at runtime
+	TailCall(&'static str),
+	/// Call function by a handle, created by FuncRef. Invalid handle is an error
+	CallIndirect(OperandType),
+	/// Store an opaque handle of a function into dst
+	FuncRef(OperandType, &'static str),
 	/// Return from function to previous execution place
 	Return,
 //============================== JUMPS ========================================
@@ -33,12 +57,486 @@ pub enum Opcode {
 	JumpEqual(&'static str, OperandType, OperandType),
 	/// Jump if arg1 != arg2
 	JumpNotEqual(&'static str, OperandType, OperandType),
+	/// Jump if arg1 < arg2, both compared as u64
+	JumpBelowUnsigned(&'static str, OperandType, OperandType),
+	/// Jump if arg1 <= arg2, both compared as u64
+	JumpBelowEqualUnsigned(&'static str, OperandType, OperandType),
+	/// Jump if arg1 > arg2, both compared as u64
+	JumpAboveUnsigned(&'static str, OperandType, OperandType),
+	/// Jump if arg1 >= arg2, both compared as u64
+	JumpAboveEqualUnsigned(&'static str, OperandType, OperandType),
+	/// Jump if floating arguments can't be ordered, i.e. at least one is NaN.
+	/// All other relational jumps are not taken for such arguments, except JumpNotEqual
+	JumpUnordered(&'static str, OperandType, OperandType),
+	/// Compare two integer operands (arg1 - arg2) and set flags, result is dropped
+	Compare(OperandType, OperandType),
+	/// Jump if flags match a condition
+	JumpIf(Condition, &'static str),
+	/// Jump to a label from the list by integer index, or to the default label if index is out of the list
+	Switch(OperandType, &'static [&'static str], &'static str),
+//============================ EXCEPTIONS =====================================
+// Throw and runtime traps, like division by zero or out of bounds memory access, raise an exception.
+// Code of a trap is the exit code of its Error. Exception unwinds the call stack until
+// an opcode is in a protected range, then execution continues at the handler.
+// Unwinding from a coroutine finishes it and continues in the code that resumed it.
+// Uncaught trap ends the run with its Error, uncaught Throw with Error::UncaughtException.
+	/// Declare opcodes from start label (inclusive) to end label (exclusive) protected by a handler label
+	/// (third argument). Does nothing at runtime. The innermost range wins if ranges are nested
+	Protect(&'static str, &'static str, &'static str),
+	/// Raise an exception with a code
+	Throw(OperandType),
+	/// Store code of the last raised exception into dst
+	Catch(OperandType),
 //============================== MOVES ========================================
 	/// Move values into registers (or memory). Destination can't be a constant
 	Move(OperandType, OperandType),
+	/// Load a value of given format from address (second argument) into dst, extending it to 64 bits.
+	/// Address is either an integer value or a memory operand, e.g. [r1 + 4]
+	Load(OperandType, OperandType, MemoryFormat),
+	/// Store a value (second argument) in given format at address (first argument), truncating it.
+	/// Address is either an integer value or a memory operand, e.g. [r1 + 4]
+	Store(OperandType, OperandType, MemoryFormat),
+	/// dst = arg1 if condition is not zero, arg2 otherwise
+	Select(OperandType, OperandType, OperandType, OperandType),
+	/// dst = src if arg1 < arg2, otherwise dst is unchanged
+	MoveIfBelow(OperandType, OperandType, OperandType, OperandType),
+	/// dst = src if arg1 <= arg2, otherwise dst is unchanged
+	MoveIfBelowEqual(OperandType, OperandType, OperandType, OperandType),
+	/// dst = src if arg1 > arg2, otherwise dst is unchanged
+	MoveIfAbove(OperandType, OperandType, OperandType, OperandType),
+	/// dst = src if arg1 >= arg2, otherwise dst is unchanged
+	MoveIfAboveEqual(OperandType, OperandType, OperandType, OperandType),
+	/// dst = src if arg1 == arg2, otherwise dst is unchanged
+	MoveIfEqual(OperandType, OperandType, OperandType, OperandType),
+	/// dst = src if arg1 != arg2, otherwise dst is unchanged
+	MoveIfNotEqual(OperandType, OperandType, OperandType, OperandType),
+	/// dst = src if floating arguments can't be ordered, otherwise dst is unchanged
+	MoveIfUnordered(OperandType, OperandType, OperandType, OperandType),
 //============================== MATH =========================================
-	/// Stores in to destination (first argument) sum of two arbitrary operands
+	/// Stores in to destination (first argument) sum of two arbitrary operands.
+	/// Integer addition wraps around on overflow and sets flags
 	Add(OperandType, OperandType, OperandType),
-	/// Stores in to destination (first argument) sub of two arbitrary operands
+	/// Stores in to destination (first argument) sub of two arbitrary operands.
+	/// Integer subtraction wraps around on overflow and sets flags
 	Sub(OperandType, OperandType, OperandType),
-}
\ No newline at end of file
+	/// dst = arg1 + arg2 + carry flag. Integers only, sets flags
+	AddCarry(OperandType, OperandType, OperandType),
+	/// dst = arg1 - arg2 - carry flag. Integers only, sets flags
+	SubBorrow(OperandType, OperandType, OperandType),
+	/// dst = arg1 * arg2. Integer multiplication wraps around on overflow and sets flags
+	Mul(OperandType, OperandType, OperandType),
+	/// dst = arg1 / arg2. Integer division by zero is an error
+	Div(OperandType, OperandType, OperandType),
+	/// dst = |src|
+	Abs(OperandType, OperandType),
+	/// dst = -src
+	Neg(OperandType, OperandType),
+	/// dst = min(arg1, arg2)
+	Min(OperandType, OperandType, OperandType),
+	/// dst = max(arg1, arg2)
+	Max(OperandType, OperandType, OperandType),
+//=========================== FLOATING MATH ===================================
+// All of them accept only floating operands. Sqrt, Floor, Ceil, Round and FusedMulAdd are exactly
+// rounded IEEE 754 operations, like Add, Sub, Mul and Div, so their results are the same everywhere.
+// Sin, Cos, Exp, Ln and Pow use the platform math library and may differ in the last bits.
+	#[cfg(feature = "float")]
+	/// dst = square root of src
+	Sqrt(OperandType, OperandType),
+	#[cfg(feature = "float")]
+	/// dst = largest integer value <= src
+	Floor(OperandType, OperandType),
+	#[cfg(feature = "float")]
+	/// dst = smallest integer value >= src
+	Ceil(OperandType, OperandType),
+	#[cfg(feature = "float")]
+	/// dst = nearest integer value, half-way cases are rounded away from zero
+	Round(OperandType, OperandType),
+	#[cfg(feature = "float")]
+	/// dst = arg1 * arg2 + arg3 with a single rounding
+	FusedMulAdd(OperandType, OperandType, OperandType, OperandType),
+	#[cfg(feature = "float")]
+	/// dst = sin(src), src in radians
+	Sin(OperandType, OperandType),
+	#[cfg(feature = "float")]
+	/// dst = cos(src), src in radians
+	Cos(OperandType, OperandType),
+	#[cfg(feature = "float")]
+	/// dst = e^src
+	Exp(OperandType, OperandType),
+	#[cfg(feature = "float")]
+	/// dst = natural logarithm of src
+	Ln(OperandType, OperandType),
+	#[cfg(feature = "float")]
+	/// dst = arg1^arg2
+	Pow(OperandType, OperandType, OperandType),
+//================================ HEAP =======================================
+// Heap occupies memory after the data section. Pointers are plain integers, 0 is never allocated.
+	/// Allocate a zeroed block of size (second argument) bytes and store its address into dst
+	Alloc(OperandType, OperandType),
+	/// Release a block. Freeing it again is an error
+	Free(OperandType),
+	/// dst = address of the block (second argument) resized to size (third argument).
+	/// Content is kept, the block may be moved
+	Realloc(OperandType, OperandType, OperandType),
+//================================ TASKS ======================================
+// Tasks are green threads with own registers, flags and call stack, memory is shared.
+// Scheduling is cooperative: a task runs until it yields, blocks or finishes,
+// then the next ready task is picked in order of creation. The run ends when the entry function returns.
+	/// Start a function (second argument) as a new task and store its handle into dst.
+	/// Arguments are copied into r0, r1, ... of the task, their number must match function signature
+	Spawn(OperandType, &'static str, &'static [OperandType]),
+	/// Let other tasks run
+	Yield,
+	/// Wait until a task finishes and copy its results into r0, r1, ...
+	Join(OperandType),
+	/// Create a coroutine of a function (second argument) and store its handle into dst.
+	/// Coroutine has own registers and call stack, arguments are copied into its r0, r1, ...
+	/// It doesn't run until resumed
+	CoroutineNew(OperandType, &'static str, &'static [OperandType]),
+	/// Run a coroutine until it yields or returns. On yield status (first argument) is 1
+	/// and dst (second argument) receives the value, on return status is 0 and dst is unchanged.
+	/// Resuming a finished or running coroutine is an error
+	Resume(OperandType, OperandType, OperandType),
+	/// Suspend the current coroutine and pass a value to the code that resumed it
+	YieldValue(OperandType),
+	/// Create a channel of integers with capacity (second argument) and store its handle into dst.
+	/// Zero capacity means unbounded
+	ChanNew(OperandType, OperandType),
+	/// Send a value (second argument) into a channel, wait while it is full.
+	/// Sending into a closed channel is an error
+	Send(OperandType, OperandType),
+	/// Receive a value from a channel into dst, wait while it is empty.
+	/// Receiving from a closed and empty channel is an error
+	Recv(OperandType, OperandType),
+	/// Send without waiting. Status (first argument) is 1 if sent, 0 if full, -1 if closed
+	TrySend(OperandType, OperandType, OperandType),
+	/// Receive without waiting into dst (second argument).
+	/// Status (first argument) is 1 if received, 0 if empty, -1 if closed and empty
+	TryRecv(OperandType, OperandType, OperandType),
+	/// Close a channel. Values sent before can still be received
+	Close(OperandType),
+//=============================== ATOMICS =====================================
+// Atomic opcodes access 64-bit words at addresses aligned to 8 bytes, address is given
+// like in Load and Store. All of them are sequentially consistent: an opcode is never
+// interrupted by another task, and tasks see memory changes in the order they were made.
+// So Fence only marks ordering points in a program, it is not needed for correctness.
+	/// dst = word at address (second argument)
+	AtomicLoad(OperandType, OperandType),
+	/// Store a value (second argument) at address (first argument)
+	AtomicStore(OperandType, OperandType),
+	/// dst = word at address (second argument). If it equals expected value (third argument),
+	/// replace it with a new one (fourth argument). Flags are set as by Compare(old value, expected),
+	/// so JumpIf equal is taken on success
+	CompareExchange(OperandType, OperandType, OperandType, OperandType),
+	/// dst = word at address (second argument), then add a value (third argument) to it in memory
+	FetchAdd(OperandType, OperandType, OperandType),
+	/// dst = word at address (second argument), then bitwise and it with a value (third argument)
+	FetchAnd(OperandType, OperandType, OperandType),
+	/// dst = word at address (second argument), then bitwise or it with a value (third argument)
+	FetchOr(OperandType, OperandType, OperandType),
+	/// dst = word at address (second argument), then bitwise xor it with a value (third argument)
+	FetchXor(OperandType, OperandType, OperandType),
+	/// Memory ordering point, see above
+	Fence,
+//============================ INPUT/OUTPUT ===================================
+	/// Print integer operand
+	PrintInteger(OperandType),
+	/// Print a string literal
+	PrintString(&'static str),
+	/// Read integer from input and store it into destination
+	ReadInteger(OperandType),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+/// Opcode argument in a generic form.
+/// Used to convert opcodes from and into text or bytecode.
+pub enum Argument {
+	/// Any operand.
+	Operand(OperandType),
+	/// Function or label name, or a string literal.
+	Name(&'static str),
+	/// List of arguments, e.g. data values.
+	List(Vec<Argument>),
+}
+
+impl Opcode {
+	/// Split opcode into a mnemonic and a list of arguments.
+	pub fn to_parts(&self) -> (&'static str, Vec<Argument>) {
+		use self::Argument::{Name as N, Operand as O};
+		use operand::OperandType::IntegerConstant;
+		match *self {
+			Opcode::Registers(integer, floating)   => ("registers", vec![O(IntegerConstant(integer as i64)), O(IntegerConstant(floating as i64))]),
+			Opcode::Data(name, format, values)     => ("data", vec![N(name), N(format.name()), data_list(values)]),
+			Opcode::ReadOnlyData(name, format, values) => ("read_only_data", vec![N(name), N(format.name()), data_list(values)]),
+			Opcode::FunctionStart(name)            => ("function", vec![N(name)]),
+			Opcode::FunctionEnd                    => ("end_function", vec![]),
+			Opcode::Signature(args, results)       => ("signature", vec![O(IntegerConstant(args as i64)), O(IntegerConstant(results as i64))]),
+			Opcode::Call(name)                     => ("call", vec![N(name)]),
+			Opcode::TailCall(name)                 => ("tail_call", vec![N(name)]),
+			Opcode::CallIndirect(handle)           => ("call_indirect", vec![O(handle)]),
+			Opcode::FuncRef(dst, name)             => ("func_ref", vec![O(dst), N(name)]),
+			Opcode::Return                         => ("return", vec![]),
+			Opcode::Label(name)                    => ("label", vec![N(name)]),
+			Opcode::Jump(name)                     => ("jump", vec![N(name)]),
+			Opcode::JumpZero(name, a)              => ("jump_zero", vec![N(name), O(a)]),
+			Opcode::JumpNotZero(name, a)           => ("jump_not_zero", vec![N(name), O(a)]),
+			Opcode::JumpBelow(name, a, b)          => ("jump_below", vec![N(name), O(a), O(b)]),
+			Opcode::JumpBelowEqual(name, a, b)     => ("jump_below_equal", vec![N(name), O(a), O(b)]),
+			Opcode::JumpAbove(name, a, b)          => ("jump_above", vec![N(name), O(a), O(b)]),
+			Opcode::JumpAboveEqual(name, a, b)     => ("jump_above_equal", vec![N(name), O(a), O(b)]),
+			Opcode::JumpEqual(name, a, b)          => ("jump_equal", vec![N(name), O(a), O(b)]),
+			Opcode::JumpNotEqual(name, a, b)       => ("jump_not_equal", vec![N(name), O(a), O(b)]),
+			Opcode::JumpBelowUnsigned(name, a, b)  => ("jump_below_unsigned", vec![N(name), O(a), O(b)]),
+			Opcode::JumpBelowEqualUnsigned(name, a, b) => ("jump_below_equal_unsigned", vec![N(name), O(a), O(b)]),
+			Opcode::JumpAboveUnsigned(name, a, b)  => ("jump_above_unsigned", vec![N(name), O(a), O(b)]),
+			Opcode::JumpAboveEqualUnsigned(name, a, b) => ("jump_above_equal_unsigned", vec![N(name), O(a), O(b)]),
+			Opcode::JumpUnordered(name, a, b)      => ("jump_unordered", vec![N(name), O(a), O(b)]),
+			Opcode::Compare(a, b)                  => ("compare", vec![O(a), O(b)]),
+			Opcode::JumpIf(condition, name)        => ("jump_if", vec![N(condition.name()), N(name)]),
+			Opcode::Switch(index, labels, default) => ("switch", vec![O(index), name_list(labels), N(default)]),
+			Opcode::Protect(start, end, handler)   => ("protect", vec![N(start), N(end), N(handler)]),
+			Opcode::Throw(code)                    => ("throw", vec![O(code)]),
+			Opcode::Catch(dst)                     => ("catch", vec![O(dst)]),
+			Opcode::Move(dst, src)                 => ("move", vec![O(dst), O(src)]),
+			Opcode::Load(dst, address, format)     => ("load", vec![O(dst), O(address), N(format.name())]),
+			Opcode::Store(address, src, format)    => ("store", vec![O(address), O(src), N(format.name())]),
+			Opcode::Select(dst, c, a, b)           => ("select", vec![O(dst), O(c), O(a), O(b)]),
+			Opcode::MoveIfBelow(dst, src, a, b)    => ("move_if_below", vec![O(dst), O(src), O(a), O(b)]),
+			Opcode::MoveIfBelowEqual(dst, src, a, b) => ("move_if_below_equal", vec![O(dst), O(src), O(a), O(b)]),
+			Opcode::MoveIfAbove(dst, src, a, b)    => ("move_if_above", vec![O(dst), O(src), O(a), O(b)]),
+			Opcode::MoveIfAboveEqual(dst, src, a, b) => ("move_if_above_equal", vec![O(dst), O(src), O(a), O(b)]),
+			Opcode::MoveIfEqual(dst, src, a, b)    => ("move_if_equal", vec![O(dst), O(src), O(a), O(b)]),
+			Opcode::MoveIfNotEqual(dst, src, a, b) => ("move_if_not_equal", vec![O(dst), O(src), O(a), O(b)]),
+			Opcode::MoveIfUnordered(dst, src, a, b) => ("move_if_unordered", vec![O(dst), O(src), O(a), O(b)]),
+			Opcode::Add(dst, a, b)                 => ("add", vec![O(dst), O(a), O(b)]),
+			Opcode::Sub(dst, a, b)                 => ("sub", vec![O(dst), O(a), O(b)]),
+			Opcode::AddCarry(dst, a, b)            => ("add_carry", vec![O(dst), O(a), O(b)]),
+			Opcode::SubBorrow(dst, a, b)           => ("sub_borrow", vec![O(dst), O(a), O(b)]),
+			Opcode::Mul(dst, a, b)                 => ("mul", vec![O(dst), O(a), O(b)]),
+			Opcode::Div(dst, a, b)                 => ("div", vec![O(dst), O(a), O(b)]),
+			Opcode::Abs(dst, src)                  => ("abs", vec![O(dst), O(src)]),
+			Opcode::Neg(dst, src)                  => ("neg", vec![O(dst), O(src)]),
+			Opcode::Min(dst, a, b)                 => ("min", vec![O(dst), O(a), O(b)]),
+			Opcode::Max(dst, a, b)                 => ("max", vec![O(dst), O(a), O(b)]),
+			#[cfg(feature = "float")]
+			Opcode::Sqrt(dst, src)                 => ("sqrt", vec![O(dst), O(src)]),
+			#[cfg(feature = "float")]
+			Opcode::Floor(dst, src)                => ("floor", vec![O(dst), O(src)]),
+			#[cfg(feature = "float")]
+			Opcode::Ceil(dst, src)                 => ("ceil", vec![O(dst), O(src)]),
+			#[cfg(feature = "float")]
+			Opcode::Round(dst, src)                => ("round", vec![O(dst), O(src)]),
+			#[cfg(feature = "float")]
+			Opcode::FusedMulAdd(dst, a, b, c)      => ("fused_mul_add", vec![O(dst), O(a), O(b), O(c)]),
+			#[cfg(feature = "float")]
+			Opcode::Sin(dst, src)                  => ("sin", vec![O(dst), O(src)]),
+			#[cfg(feature = "float")]
+			Opcode::Cos(dst, src)                  => ("cos", vec![O(dst), O(src)]),
+			#[cfg(feature = "float")]
+			Opcode::Exp(dst, src)                  => ("exp", vec![O(dst), O(src)]),
+			#[cfg(feature = "float")]
+			Opcode::Ln(dst, src)                   => ("ln", vec![O(dst), O(src)]),
+			#[cfg(feature = "float")]
+			Opcode::Pow(dst, a, b)                 => ("pow", vec![O(dst), O(a), O(b)]),
+			Opcode::Alloc(dst, size)               => ("alloc", vec![O(dst), O(size)]),
+			Opcode::Free(ptr)                      => ("free", vec![O(ptr)]),
+			Opcode::Realloc(dst, ptr, size)        => ("realloc", vec![O(dst), O(ptr), O(size)]),
+			Opcode::Spawn(dst, name, args)         => ("spawn", vec![O(dst), N(name), operand_list(args)]),
+			Opcode::Yield                          => ("yield", vec![]),
+			Opcode::Join(handle)                   => ("join", vec![O(handle)]),
+			Opcode::CoroutineNew(dst, name, args)  => ("coroutine_new", vec![O(dst), N(name), operand_list(args)]),
+			Opcode::Resume(status, dst, handle)    => ("resume", vec![O(status), O(dst), O(handle)]),
+			Opcode::YieldValue(src)                => ("yield_value", vec![O(src)]),
+			Opcode::ChanNew(dst, capacity)         => ("chan_new", vec![O(dst), O(capacity)]),
+			Opcode::Send(channel, src)             => ("send", vec![O(channel), O(src)]),
+			Opcode::Recv(dst, channel)             => ("recv", vec![O(dst), O(channel)]),
+			Opcode::TrySend(status, channel, src)  => ("try_send", vec![O(status), O(channel), O(src)]),
+			Opcode::TryRecv(status, dst, channel)  => ("try_recv", vec![O(status), O(dst), O(channel)]),
+			Opcode::Close(channel)                 => ("close", vec![O(channel)]),
+			Opcode::AtomicLoad(dst, address)       => ("atomic_load", vec![O(dst), O(address)]),
+			Opcode::AtomicStore(address, src)      => ("atomic_store", vec![O(address), O(src)]),
+			Opcode::CompareExchange(dst, address, expected, new) =>
+				("compare_exchange", vec![O(dst), O(address), O(expected), O(new)]),
+			Opcode::FetchAdd(dst, address, src)    => ("fetch_add", vec![O(dst), O(address), O(src)]),
+			Opcode::FetchAnd(dst, address, src)    => ("fetch_and", vec![O(dst), O(address), O(src)]),
+			Opcode::FetchOr(dst, address, src)     => ("fetch_or", vec![O(dst), O(address), O(src)]),
+			Opcode::FetchXor(dst, address, src)    => ("fetch_xor", vec![O(dst), O(address), O(src)]),
+			Opcode::Fence                          => ("fence", vec![]),
+			Opcode::PrintInteger(src)              => ("print_integer", vec![O(src)]),
+			Opcode::PrintString(text)              => ("print_string", vec![N(text)]),
+			Opcode::ReadInteger(dst)               => ("read_integer", vec![O(dst)]),
+		}
+	}
+
+	/// Build an opcode from a mnemonic and a list of arguments.
+	/// Reverse operation for `to_parts`.
+	pub fn from_parts(mnemonic: &str, args: &[Argument]) -> Result<Opcode, Error> {
+		use self::Argument::{Name as N, Operand as O};
+		use operand::OperandType::IntegerConstant;
+		let opcode = match (mnemonic, args) {
+			("registers", &[O(IntegerConstant(integer)), O(IntegerConstant(floating))])
+					if integer >= 0 && floating >= 0     => Opcode::Registers(integer as usize, floating as usize),
+			("data", &[N(name), N(format), ref values])  => {
+				let format = MemoryFormat::from_name(format).ok_or(Error::InvalidArgument)?;
+				Opcode::Data(name, format, data_values(format, values)?)
+			},
+			("read_only_data", &[N(name), N(format), ref values]) => {
+				let format = MemoryFormat::from_name(format).ok_or(Error::InvalidArgument)?;
+				Opcode::ReadOnlyData(name, format, data_values(format, values)?)
+			},
+			("function", &[N(name)])                     => Opcode::FunctionStart(name),
+			("end_function", &[])                        => Opcode::FunctionEnd,
+			("signature", &[O(IntegerConstant(args)), O(IntegerConstant(results))])
+					if args >= 0 && results >= 0         => Opcode::Signature(args as usize, results as usize),
+			("call", &[N(name)])                         => Opcode::Call(name),
+			("tail_call", &[N(name)])                    => Opcode::TailCall(name),
+			("call_indirect", &[O(handle)])              => Opcode::CallIndirect(handle),
+			("func_ref", &[O(dst), N(name)])             => Opcode::FuncRef(dst, name),
+			("return", &[])                              => Opcode::Return,
+			("label", &[N(name)])                        => Opcode::Label(name),
+			("jump", &[N(name)])                         => Opcode::Jump(name),
+			("jump_zero", &[N(name), O(a)])              => Opcode::JumpZero(name, a),
+			("jump_not_zero", &[N(name), O(a)])          => Opcode::JumpNotZero(name, a),
+			("jump_below", &[N(name), O(a), O(b)])       => Opcode::JumpBelow(name, a, b),
+			("jump_below_equal", &[N(name), O(a), O(b)]) => Opcode::JumpBelowEqual(name, a, b),
+			("jump_above", &[N(name), O(a), O(b)])       => Opcode::JumpAbove(name, a, b),
+			("jump_above_equal", &[N(name), O(a), O(b)]) => Opcode::JumpAboveEqual(name, a, b),
+			("jump_equal", &[N(name), O(a), O(b)])       => Opcode::JumpEqual(name, a, b),
+			("jump_not_equal", &[N(name), O(a), O(b)])   => Opcode::JumpNotEqual(name, a, b),
+			("jump_below_unsigned", &[N(name), O(a), O(b)])       => Opcode::JumpBelowUnsigned(name, a, b),
+			("jump_below_equal_unsigned", &[N(name), O(a), O(b)]) => Opcode::JumpBelowEqualUnsigned(name, a, b),
+			("jump_above_unsigned", &[N(name), O(a), O(b)])       => Opcode::JumpAboveUnsigned(name, a, b),
+			("jump_above_equal_unsigned", &[N(name), O(a), O(b)]) => Opcode::JumpAboveEqualUnsigned(name, a, b),
+			("jump_unordered", &[N(name), O(a), O(b)])   => Opcode::JumpUnordered(name, a, b),
+			("compare", &[O(a), O(b)])                   => Opcode::Compare(a, b),
+			("jump_if", &[N(condition), N(name)])        =>
+					Opcode::JumpIf(Condition::from_name(condition).ok_or(Error::InvalidArgument)?, name),
+			("switch", &[O(index), ref labels, N(default)]) => Opcode::Switch(index, names(labels)?, default),
+			("protect", &[N(start), N(end), N(handler)]) => Opcode::Protect(start, end, handler),
+			("throw", &[O(code)])                        => Opcode::Throw(code),
+			("catch", &[O(dst)])                         => Opcode::Catch(dst),
+			("move", &[O(dst), O(src)])                  => Opcode::Move(dst, src),
+			("load", &[O(dst), O(address), N(format)])   =>
+					Opcode::Load(dst, address, MemoryFormat::from_name(format).ok_or(Error::InvalidArgument)?),
+			("store", &[O(address), O(src), N(format)])  =>
+					Opcode::Store(address, src, MemoryFormat::from_name(format).ok_or(Error::InvalidArgument)?),
+			("select", &[O(dst), O(c), O(a), O(b)])      => Opcode::Select(dst, c, a, b),
+			("move_if_below", &[O(dst), O(src), O(a), O(b)])       => Opcode::MoveIfBelow(dst, src, a, b),
+			("move_if_below_equal", &[O(dst), O(src), O(a), O(b)]) => Opcode::MoveIfBelowEqual(dst, src, a, b),
+			("move_if_above", &[O(dst), O(src), O(a), O(b)])       => Opcode::MoveIfAbove(dst, src, a, b),
+			("move_if_above_equal", &[O(dst), O(src), O(a), O(b)]) => Opcode::MoveIfAboveEqual(dst, src, a, b),
+			("move_if_equal", &[O(dst), O(src), O(a), O(b)])       => Opcode::MoveIfEqual(dst, src, a, b),
+			("move_if_not_equal", &[O(dst), O(src), O(a), O(b)])   => Opcode::MoveIfNotEqual(dst, src, a, b),
+			("move_if_unordered", &[O(dst), O(src), O(a), O(b)])   => Opcode::MoveIfUnordered(dst, src, a, b),
+			("add", &[O(dst), O(a), O(b)])               => Opcode::Add(dst, a, b),
+			("sub", &[O(dst), O(a), O(b)])               => Opcode::Sub(dst, a, b),
+			("add_carry", &[O(dst), O(a), O(b)])         => Opcode::AddCarry(dst, a, b),
+			("sub_borrow", &[O(dst), O(a), O(b)])        => Opcode::SubBorrow(dst, a, b),
+			("mul", &[O(dst), O(a), O(b)])               => Opcode::Mul(dst, a, b),
+			("div", &[O(dst), O(a), O(b)])               => Opcode::Div(dst, a, b),
+			("abs", &[O(dst), O(src)])                   => Opcode::Abs(dst, src),
+			("neg", &[O(dst), O(src)])                   => Opcode::Neg(dst, src),
+			("min", &[O(dst), O(a), O(b)])               => Opcode::Min(dst, a, b),
+			("max", &[O(dst), O(a), O(b)])               => Opcode::Max(dst, a, b),
+			#[cfg(feature = "float")]
+			("sqrt", &[O(dst), O(src)])                  => Opcode::Sqrt(dst, src),
+			#[cfg(feature = "float")]
+			("floor", &[O(dst), O(src)])                 => Opcode::Floor(dst, src),
+			#[cfg(feature = "float")]
+			("ceil", &[O(dst), O(src)])                  => Opcode::Ceil(dst, src),
+			#[cfg(feature = "float")]
+			("round", &[O(dst), O(src)])                 => Opcode::Round(dst, src),
+			#[cfg(feature = "float")]
+			("fused_mul_add", &[O(dst), O(a), O(b), O(c)]) => Opcode::FusedMulAdd(dst, a, b, c),
+			#[cfg(feature = "float")]
+			("sin", &[O(dst), O(src)])                   => Opcode::Sin(dst, src),
+			#[cfg(feature = "float")]
+			("cos", &[O(dst), O(src)])                   => Opcode::Cos(dst, src),
+			#[cfg(feature = "float")]
+			("exp", &[O(dst), O(src)])                   => Opcode::Exp(dst, src),
+			#[cfg(feature = "float")]
+			("ln", &[O(dst), O(src)])                    => Opcode::Ln(dst, src),
+			#[cfg(feature = "float")]
+			("pow", &[O(dst), O(a), O(b)])               => Opcode::Pow(dst, a, b),
+			("alloc", &[O(dst), O(size)])                => Opcode::Alloc(dst, size),
+			("free", &[O(ptr)])                          => Opcode::Free(ptr),
+			("realloc", &[O(dst), O(ptr), O(size)])      => Opcode::Realloc(dst, ptr, size),
+			("spawn", &[O(dst), N(name), ref args])      => Opcode::Spawn(dst, name, operands(args)?),
+			("yield", &[])                               => Opcode::Yield,
+			("join", &[O(handle)])                       => Opcode::Join(handle),
+			("coroutine_new", &[O(dst), N(name), ref args]) => Opcode::CoroutineNew(dst, name, operands(args)?),
+			("resume", &[O(status), O(dst), O(handle)])  => Opcode::Resume(status, dst, handle),
+			("yield_value", &[O(src)])                   => Opcode::YieldValue(src),
+			("chan_new", &[O(dst), O(capacity)])         => Opcode::ChanNew(dst, capacity),
+			("send", &[O(channel), O(src)])              => Opcode::Send(channel, src),
+			("recv", &[O(dst), O(channel)])              => Opcode::Recv(dst, channel),
+			("try_send", &[O(status), O(channel), O(src)]) => Opcode::TrySend(status, channel, src),
+			("try_recv", &[O(status), O(dst), O(channel)]) => Opcode::TryRecv(status, dst, channel),
+			("close", &[O(channel)])                     => Opcode::Close(channel),
+			("atomic_load", &[O(dst), O(address)])       => Opcode::AtomicLoad(dst, address),
+			("atomic_store", &[O(address), O(src)])      => Opcode::AtomicStore(address, src),
+			("compare_exchange", &[O(dst), O(address), O(expected), O(new)]) =>
+					Opcode::CompareExchange(dst, address, expected, new),
+			("fetch_add", &[O(dst), O(address), O(src)]) => Opcode::FetchAdd(dst, address, src),
+			("fetch_and", &[O(dst), O(address), O(src)]) => Opcode::FetchAnd(dst, address, src),
+			("fetch_or", &[O(dst), O(address), O(src)])  => Opcode::FetchOr(dst, address, src),
+			("fetch_xor", &[O(dst), O(address), O(src)]) => Opcode::FetchXor(dst, address, src),
+			("fence", &[])                               => Opcode::Fence,
+			("print_integer", &[O(src)])                 => Opcode::PrintInteger(src),
+			("print_string", &[N(text)])                 => Opcode::PrintString(text),
+			("read_integer", &[O(dst)])                  => Opcode::ReadInteger(dst),
+			_ => return Err(Error::MalformedOpcode),
+		};
+		Ok(opcode)
+	}
+}
+
+fn data_list(values: &[i64]) -> Argument {
+	Argument::List(values.iter().map(|&value| Argument::Operand(OperandType::IntegerConstant(value))).collect())
+}
+
+/// Values of a data block: list of integer constants or a string for single byte formats.
+/// Opcodes refer to values as &'static slice, so they are leaked once.
+fn data_values(format: MemoryFormat, values: &Argument) -> Result<&'static [i64], Error> {
+	let result: Vec<i64> = match *values {
+		Argument::List(ref items) => items.iter().map(|item| match *item {
+			Argument::Operand(OperandType::IntegerConstant(value)) => Ok(value),
+			_ => Err(Error::InvalidArgument),
+		}).collect::<Result<Vec<i64>, Error>>()?,
+		Argument::Name(text) if format.width() == 1 => text.bytes().map(|byte| byte as i64).collect(),
+		_ => return Err(Error::InvalidArgument),
+	};
+	Ok(Box::leak(result.into_boxed_slice()))
+}
+
+fn name_list(names: &[&'static str]) -> Argument {
+	Argument::List(names.iter().map(|&name| Argument::Name(name)).collect())
+}
+
+/// List of names, leaked like data values.
+fn names(list: &Argument) -> Result<&'static [&'static str], Error> {
+	let result: Vec<&'static str> = match *list {
+		Argument::List(ref items) => items.iter().map(|item| match *item {
+			Argument::Name(name) => Ok(name),
+			_ => Err(Error::InvalidArgument),
+		}).collect::<Result<Vec<&'static str>, Error>>()?,
+		_ => return Err(Error::InvalidArgument),
+	};
+	Ok(Box::leak(result.into_boxed_slice()))
+}
+
+fn operand_list(operands: &[OperandType]) -> Argument {
+	Argument::List(operands.iter().map(|&operand| Argument::Operand(operand)).collect())
+}
+
+/// List of operands, leaked like data values.
+fn operands(list: &Argument) -> Result<&'static [OperandType], Error> {
+	let result: Vec<OperandType> = match *list {
+		Argument::List(ref items) => items.iter().map(|item| match *item {
+			Argument::Operand(operand) => Ok(operand),
+			_ => Err(Error::InvalidArgument),
+		}).collect::<Result<Vec<OperandType>, Error>>()?,
+		_ => return Err(Error::InvalidArgument),
+	};
+	Ok(Box::leak(result.into_boxed_slice()))
+}
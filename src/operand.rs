@@ -1,49 +1,129 @@
+use std::cmp::Ordering;
 use std::ops::Add;
+use std::ops::Div;
+use std::ops::Mul;
+use std::ops::Neg;
 use std::ops::Sub;
 
 use error::Error;
 
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, PartialEq)]
 /// Operand type. 
 pub enum OperandType {
 	/// General purpose register of given number.
 	IntegerRegister(usize),
-	#[cfg(float)]
+	#[cfg(feature = "float")]
 	/// Floating point register.
 	FloatingRegister(usize),
-	/// Address in the memory to read data from. Data size equals register size.
+	/// Address in the memory to read data from. Data is a 64-bit little-endian integer.
+	/// Use Load and Store opcodes for other formats.
 	Memory(usize),
+	/// Same as Memory, but address is [base register + displacement].
+	MemoryBase(usize, i64),
+	/// Same as Memory, but address is [base register + index register * scale + displacement].
+	MemoryIndex(usize, usize, u8, i64),
+	/// Address of a data block, i.e. an integer constant.
+	/// Replaced by IntegerConstant when a Program is loaded.
+	Symbol(&'static str),
+	/// Memory at [data block + displacement].
+	/// Replaced by Memory when a Program is loaded.
+	MemorySymbol(&'static str, i64),
 	/// u64 constant.
 	IntegerConstant(i64),
-	#[cfg(float)]
+	#[cfg(feature = "float")]
 	/// f64 constant.
 	FloatingConstant(f64),
 }
 
+impl OperandType {
+	/// Operand holds (or points to) a floating point value.
+	pub fn is_floating(self) -> bool {
+		match self {
+			#[cfg(feature = "float")]
+			OperandType::FloatingRegister(_) | OperandType::FloatingConstant(_) => true,
+			_ => false,
+		}
+	}
+
+	/// Zero constant of the same type as operand.
+	pub fn zero_like(self) -> OperandType {
+		#[cfg(feature = "float")]
+		{
+			if self.is_floating() {
+				return OperandType::FloatingConstant(0.0);
+			}
+		}
+		OperandType::IntegerConstant(0)
+	}
+}
+
 #[derive(Debug, Clone, Copy)]
 /// Operand value. Returned by prefetcher and differs by data type (like Either).
 pub enum OperandValue {
 	IntegerValue(i64),
-	#[cfg(float)]
+	#[cfg(feature = "float")]
 	FloatingValue(f64),
-	MemoryAddress(usize),
 }
 
 impl OperandValue {
 	pub fn unwrap_integer(self) -> Result<i64, Error>{
 		match self {
 			OperandValue::IntegerValue(val) => Ok(val),
+			#[cfg(feature = "float")]
 			_ => Err(Error::UnsupportedOperand),
 		}
 	}
 
-	#[cfg(float)]
+	/// Compare two values of the same type.
+	/// Floating values follow IEEE 754: None means unordered (at least one of them is NaN).
+	pub fn compare(self, other: OperandValue) -> Result<Option<Ordering>, Error> {
+		match self {
+			OperandValue::IntegerValue(val) => Ok(Some(val.cmp(&other.unwrap_integer()?))),
+			#[cfg(feature = "float")]
+			OperandValue::FloatingValue(val) => Ok(val.partial_cmp(&other.unwrap_floating()?)),
+		}
+	}
+
+	#[cfg(feature = "float")]
 	pub fn unwrap_floating(self) -> Result<f64, Error>{
 		match self {
 			OperandValue::FloatingValue(val) => Ok(val),
 			_ => Err(Error::UnsupportedOperand),
 		}
 	}
+
+	/// Apply a floating point function. Integer values are not supported.
+	#[cfg(feature = "float")]
+	pub fn map_floating<F>(self, action: F) -> Result<OperandValue, Error> where F: FnOnce(f64) -> f64 {
+		Ok(OperandValue::FloatingValue(action(self.unwrap_floating()?)))
+	}
+
+	/// Absolute value. Integer abs of i64::MIN wraps around.
+	pub fn abs(self) -> Result<OperandValue, Error> {
+		match self {
+			OperandValue::IntegerValue(val) => Ok(OperandValue::IntegerValue(val.wrapping_abs())),
+			#[cfg(feature = "float")]
+			OperandValue::FloatingValue(val) => Ok(OperandValue::FloatingValue(val.abs())),
+		}
+	}
+
+	/// Minimum of two values. If one of floating values is NaN, the other one is returned.
+	pub fn min(self, other: OperandValue) -> Result<OperandValue, Error> {
+		match self {
+			OperandValue::IntegerValue(val) => Ok(OperandValue::IntegerValue(val.min(other.unwrap_integer()?))),
+			#[cfg(feature = "float")]
+			OperandValue::FloatingValue(val) => Ok(OperandValue::FloatingValue(val.min(other.unwrap_floating()?))),
+		}
+	}
+
+	/// Maximum of two values. If one of floating values is NaN, the other one is returned.
+	pub fn max(self, other: OperandValue) -> Result<OperandValue, Error> {
+		match self {
+			OperandValue::IntegerValue(val) => Ok(OperandValue::IntegerValue(val.max(other.unwrap_integer()?))),
+			#[cfg(feature = "float")]
+			OperandValue::FloatingValue(val) => Ok(OperandValue::FloatingValue(val.max(other.unwrap_floating()?))),
+		}
+	}
 }
 
 impl Add for OperandValue {
@@ -52,13 +132,12 @@ impl Add for OperandValue {
 	fn add(self, other: OperandValue) -> Self::Output {
 		match self {
 			OperandValue::IntegerValue(val) => {
-				Ok(OperandValue::IntegerValue(val + other.unwrap_integer()?))
+				Ok(OperandValue::IntegerValue(val.wrapping_add(other.unwrap_integer()?)))
 			},
-			#[cfg(float)]
+			#[cfg(feature = "float")]
 			OperandValue::FloatingValue(val) => {
 				Ok(OperandValue::FloatingValue(val + other.unwrap_floating()?))
 			},
-			_ => Err(Error::NotImplemented),
 		}
 	}
 }
@@ -69,13 +148,61 @@ impl Sub for OperandValue {
 	fn sub(self, other: OperandValue) -> Self::Output {
 		match self {
 			OperandValue::IntegerValue(val) => {
-				Ok(OperandValue::IntegerValue(val - other.unwrap_integer()?))
+				Ok(OperandValue::IntegerValue(val.wrapping_sub(other.unwrap_integer()?)))
 			},
-			#[cfg(float)]
+			#[cfg(feature = "float")]
 			OperandValue::FloatingValue(val) => {
 				Ok(OperandValue::FloatingValue(val - other.unwrap_floating()?))
 			},
-			_ => Err(Error::NotImplemented),
+		}
+	}
+}
+
+impl Mul for OperandValue {
+	type Output = Result<OperandValue, Error>;
+
+	fn mul(self, other: OperandValue) -> Self::Output {
+		match self {
+			OperandValue::IntegerValue(val) => {
+				Ok(OperandValue::IntegerValue(val.wrapping_mul(other.unwrap_integer()?)))
+			},
+			#[cfg(feature = "float")]
+			OperandValue::FloatingValue(val) => {
+				Ok(OperandValue::FloatingValue(val * other.unwrap_floating()?))
+			},
+		}
+	}
+}
+
+impl Div for OperandValue {
+	type Output = Result<OperandValue, Error>;
+
+	/// Integer division by zero is an error, floating one follows IEEE 754.
+	fn div(self, other: OperandValue) -> Self::Output {
+		match self {
+			OperandValue::IntegerValue(val) => {
+				let divisor = other.unwrap_integer()?;
+				if divisor == 0 {
+					return Err(Error::DivisionByZero);
+				}
+				Ok(OperandValue::IntegerValue(val.wrapping_div(divisor)))
+			},
+			#[cfg(feature = "float")]
+			OperandValue::FloatingValue(val) => {
+				Ok(OperandValue::FloatingValue(val / other.unwrap_floating()?))
+			},
+		}
+	}
+}
+
+impl Neg for OperandValue {
+	type Output = Result<OperandValue, Error>;
+
+	fn neg(self) -> Self::Output {
+		match self {
+			OperandValue::IntegerValue(val) => Ok(OperandValue::IntegerValue(val.wrapping_neg())),
+			#[cfg(feature = "float")]
+			OperandValue::FloatingValue(val) => Ok(OperandValue::FloatingValue(-val)),
 		}
 	}
 }
@@ -1,34 +1,272 @@
+use std::cmp::Ordering;
 use std::collections::HashMap;
+use std::mem;
 use std::time::Instant;
-use std::mem::{discriminant}; // discriminant will allow to compare enum variants
-use std::ops::Range;
 
 use error::Error;
+use flags::Flags;
 use operand::{OperandValue, OperandType};
 use function::Function;
+use memory::{Memory, MemoryFormat};
+use heap::Heap;
+use channel::{Channel, ChannelError};
+use io::{Io, StdIo};
 use opcode::Opcode;
+use program::Program;
+
+// Predicates of conditional jumps and moves.
+// They receive result of arguments comparison, None means unordered floating values.
+fn is_equal(o: Option<Ordering>) -> bool {
+	o == Some(Ordering::Equal)
+}
+
+fn is_not_equal(o: Option<Ordering>) -> bool {
+	o != Some(Ordering::Equal)
+}
+
+fn is_below(o: Option<Ordering>) -> bool {
+	o == Some(Ordering::Less)
+}
+
+fn is_below_equal(o: Option<Ordering>) -> bool {
+	o == Some(Ordering::Less) || o == Some(Ordering::Equal)
+}
+
+fn is_above(o: Option<Ordering>) -> bool {
+	o == Some(Ordering::Greater)
+}
+
+fn is_above_equal(o: Option<Ordering>) -> bool {
+	o == Some(Ordering::Greater) || o == Some(Ordering::Equal)
+}
+
+fn is_unordered(o: Option<Ordering>) -> bool {
+	o.is_none()
+}
+
+/// Status of TrySend and TryRecv.
+fn channel_status(result: Result<(), ChannelError>) -> i64 {
+	match result {
+		Ok(()) => 1,
+		Err(ChannelError::WouldBlock) => 0,
+		Err(ChannelError::Closed) => -1,
+	}
+}
+
+/// Size argument of Alloc and Realloc. Negative size is not a size at all.
+fn heap_size(size: i64) -> Result<usize, Error> {
+	if size < 0 {
+		Err(Error::InvalidHeapSize(size))
+	} else {
+		Ok(size as usize)
+	}
+}
+
+/// Parameters of a VM, that are fixed at construction.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct VmConfig {
+	/// Number of integer registers.
+	pub integer_registers: usize,
+	/// Number of floating registers. Ignored without "float" feature.
+	pub floating_registers: usize,
+	/// Size of memory in bytes.
+	pub memory_size: usize,
+	/// Remember freed heap blocks and fail on access to them.
+	/// Slows down every memory access, so it is meant for debugging.
+	pub debug_heap: bool,
+	/// Maximum number of nested function calls in a task or coroutine, including the entry function.
+	pub max_call_depth: usize,
+	/// Maximum total size of stack frames in a task or coroutine.
+	pub stack_size: usize,
+}
+
+impl Default for VmConfig {
+	fn default() -> VmConfig {
+		VmConfig {
+			integer_registers: 32,
+			floating_registers: 32,
+			memory_size: 64 * 1024,
+			debug_heap: false,
+			max_call_depth: 4096,
+			stack_size: 1024 * 1024,
+		}
+	}
+}
+
+/// Defines what is kept between runs of the same VM.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct RunOptions {
+	/// Zero all registers and flags before a run.
+	/// By default they are preserved, so a run can use results of the previous one.
+	pub clear_registers: bool,
+	/// Zero memory before a run. By default memory is preserved,
+	/// but program data is restored and heap starts empty in every run anyway.
+	pub clear_memory: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum TaskState {
+	Ready,
+	/// Waits for a task with given handle to finish.
+	Joining(usize),
+	/// Waits for a free place in a channel.
+	Sending(usize),
+	/// Waits for a value in a channel.
+	Receiving(usize),
+	Finished,
+}
+
+/// Registers and call stack of a task or a coroutine, that is not running right now.
+/// Running code keeps its context in the VM itself, it is moved out on switch.
+#[derive(Debug, Default)]
+struct Context {
+	/// Function the code is in.
+	function: &'static str,
+	integer_register: Vec<i64>,
+	#[cfg(feature = "float")]
+	floating_register: Vec<f64>,
+	flags: Flags,
+	opcode_pointer: usize,
+	stack_pointer: usize,
+	return_stack: Vec<(&'static str, usize, usize)>,
+	exception_code: i64,
+}
+
+#[derive(Debug)]
+struct Task {
+	state: TaskState,
+	/// Function the task was started with, defines number of results.
+	entry: &'static str,
+	/// Empty while the task is running.
+	context: Context,
+	/// Coroutines resumed by the task, empty while the task is running.
+	resumers: Vec<Resumer>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum CoroutineState {
+	Suspended,
+	Running,
+	Finished,
+}
+
+/// Suspended frame of a coroutine is kept here, not in return_stack.
+#[derive(Debug)]
+struct Coroutine {
+	state: CoroutineState,
+	/// Empty while the coroutine is running.
+	context: Context,
+}
+
+/// Code that resumed a running coroutine, it continues when the coroutine yields or returns.
+#[derive(Debug)]
+struct Resumer {
+	coroutine: usize,
+	/// Operands of Resume that receive the result.
+	status: OperandType,
+	dst: OperandType,
+	context: Context,
+}
 
 /// A simple virtual machine with a stack.
-#[derive(Default, Debug)]
+/// Call stack is always empty between runs, even if a run failed.
+/// Registers and memory keep values they had at the moment of failure, see `RunOptions`.
+#[derive(Debug)]
 pub struct ParallaxVm {
-	integer_register: [i64; 32],
-	#[cfg(float)] // Make floating point extension optional
-	floating_register: [f64; 32],
+	config: VmConfig,
+
+	integer_register: Vec<i64>,
+	#[cfg(feature = "float")] // Make floating point extension optional
+	floating_register: Vec<f64>,
 
 	opcode_pointer: usize,
 	stack_pointer: usize,
 
+	/// Set by integer Compare and arithmetic, read by JumpIf
+	flags: Flags,
+
+	memory: Memory,
+	/// Allocator of memory after program data, reset before every run
+	heap: Heap,
+
 	/// This stack holds return address and a stack frame index
 	return_stack: Vec<(&'static str, usize, usize)>,
+
+	/// All tasks of the current run, index is a handle. Entry function is task 0.
+	tasks: Vec<Task>,
+	current_task: usize,
+	/// Set when the running task yields or blocks
+	task_switch: bool,
+	/// Channels of the current run, index is a handle
+	channels: Vec<Channel>,
+	/// Coroutines of the current run, index is a handle
+	coroutines: Vec<Coroutine>,
+	/// Chain of coroutines resumed by the running task, the last one is running
+	resumers: Vec<Resumer>,
+	/// Code of the last exception raised in the running task or coroutine, read by Catch
+	exception_code: i64,
+
+	/// Maximum number of opcodes to execute in a single run
+	fuel_limit: Option<u64>,
+	run_options: RunOptions,
+
+	/// All input/output opcodes go through it
+	io: Box<dyn Io>,
+}
+
+impl Default for ParallaxVm {
+	fn default() -> ParallaxVm {
+		ParallaxVm::with_io(Box::new(StdIo))
+	}
 }
 
 impl ParallaxVm {
+	/// Create a VM that uses given input/output implementation.
+	pub fn with_io(io: Box<dyn Io>) -> ParallaxVm {
+		ParallaxVm::new(VmConfig::default(), io)
+	}
+
+	/// Create a VM with given configuration and input/output implementation.
+	pub fn new(config: VmConfig, io: Box<dyn Io>) -> ParallaxVm {
+		ParallaxVm {
+			config,
+			integer_register: vec![0; config.integer_registers],
+			#[cfg(feature = "float")]
+			floating_register: vec![0.0; config.floating_registers],
+			flags: Flags::default(),
+			memory: Memory::new(config.memory_size),
+			heap: Heap::new(0, config.memory_size, config.debug_heap),
+			opcode_pointer: 0,
+			stack_pointer: 0,
+			return_stack: Vec::new(),
+			tasks: Vec::new(),
+			current_task: 0,
+			task_switch: false,
+			channels: Vec::new(),
+			coroutines: Vec::new(),
+			resumers: Vec::new(),
+			exception_code: 0,
+			fuel_limit: None,
+			run_options: RunOptions::default(),
+			io,
+		}
+	}
+
 	/// Get value depending on operands
 	fn prefetch_operand(&self, operand: OperandType) -> Result<OperandValue, Error> {
 		match operand {
 			OperandType::IntegerRegister(n) => Ok(OperandValue::IntegerValue(self.get_int_register(n)?)),
 			OperandType::IntegerConstant(n) => Ok(OperandValue::IntegerValue(n)),
-			OperandType::Memory(_address)   => Err(Error::NotImplemented),
+			#[cfg(feature = "float")]
+			OperandType::FloatingRegister(n) => Ok(OperandValue::FloatingValue(self.get_float_register(n)?)),
+			#[cfg(feature = "float")]
+			OperandType::FloatingConstant(n) => Ok(OperandValue::FloatingValue(n)),
+			OperandType::Memory(..) | OperandType::MemoryBase(..) | OperandType::MemoryIndex(..) => {
+				let address = self.effective_address(operand)?;
+				Ok(OperandValue::IntegerValue(self.load_memory(address, MemoryFormat::WORD)?))
+			},
+			// Program replaces symbols with addresses on load.
+			OperandType::Symbol(name) | OperandType::MemorySymbol(name, _) => Err(Error::SymbolIsNotDefined(name)),
 			/*_                               => Err(Error::NotImplemented),*/
 		}
 	}
@@ -37,26 +275,114 @@ impl ParallaxVm {
 	fn store_value(&mut self, operand: OperandType, new_value: OperandValue) -> Result<(), Error> {
 		match operand {
 			OperandType::IntegerRegister(n)  => Ok(self.set_int_register(n, new_value.unwrap_integer()?))?,
-			#[cfg(float)]
+			#[cfg(feature = "float")]
 			OperandType::FloatingRegister(n) => Ok(self.set_float_register(n, new_value.unwrap_floating()?))?,
 			OperandType::IntegerConstant(_n) => Err(Error::UnsupportedOperation),
-			#[cfg(float)]
-			OperandType::FloatingConstant(n) => Err(Error::UnsupportedOperation),
-			OperandType::Memory(_address)    => Err(Error::NotImplemented),
+			#[cfg(feature = "float")]
+			OperandType::FloatingConstant(_n) => Err(Error::UnsupportedOperation),
+			OperandType::Memory(..) | OperandType::MemoryBase(..) | OperandType::MemoryIndex(..) => {
+				let address = self.effective_address(operand)?;
+				self.store_memory(address, MemoryFormat::WORD, new_value.unwrap_integer()?)
+			},
+			OperandType::Symbol(_name)       => Err(Error::UnsupportedOperation),
+			OperandType::MemorySymbol(name, _) => Err(Error::SymbolIsNotDefined(name)),
+		}
+	}
+
+	/// All memory accesses of opcodes go through it, so the heap can check them.
+	fn load_memory(&self, address: usize, format: MemoryFormat) -> Result<i64, Error> {
+		self.heap.check_access(address, format.width())?;
+		self.memory.load(address, format)
+	}
+
+	fn store_memory(&mut self, address: usize, format: MemoryFormat, value: i64) -> Result<(), Error> {
+		self.heap.check_access(address, format.width())?;
+		self.memory.store(address, format, value)
+	}
+
+	/// Address that memory operand points to.
+	/// Address arithmetic wraps around. Negative values become huge addresses,
+	/// so they fail bounds check of memory access.
+	fn effective_address(&self, operand: OperandType) -> Result<usize, Error> {
+		match operand {
+			OperandType::Memory(address) => Ok(address),
+			OperandType::MemoryBase(base, displacement) => {
+				Ok(self.get_int_register(base)?.wrapping_add(displacement) as usize)
+			},
+			OperandType::MemoryIndex(base, index, scale, displacement) => {
+				let offset: i64 = self.get_int_register(index)?.wrapping_mul(scale as i64);
+				Ok(self.get_int_register(base)?.wrapping_add(offset).wrapping_add(displacement) as usize)
+			},
+			_ => Err(Error::UnsupportedOperand),
+		}
+	}
+
+	/// Address of Load and Store: memory operand gives its effective address, other operands give their value.
+	fn memory_address(&self, operand: OperandType) -> Result<usize, Error> {
+		match operand {
+			OperandType::Memory(..) | OperandType::MemoryBase(..) | OperandType::MemoryIndex(..) =>
+				self.effective_address(operand),
+			_ => Ok(self.prefetch_operand(operand)?.unwrap_integer()? as usize),
+		}
+	}
+
+	/// Address of atomic opcodes, must be aligned to a word.
+	fn atomic_address(&self, operand: OperandType) -> Result<usize, Error> {
+		let address = self.memory_address(operand)?;
+		if address % MemoryFormat::WORD.width() != 0 {
+			return Err(Error::UnalignedAtomic(address));
+		}
+		Ok(address)
+	}
+
+	/// Generic implementation of read-modify-write atomics: dst = old value, memory = action(old, value).
+	fn fetch_generic<F>(&mut self, action: F, dst: OperandType, address: OperandType, src: OperandType)
+			-> Result<(), Error> where F: FnOnce(i64, i64) -> i64
+	{
+		let address = self.atomic_address(address)?;
+		let value = self.prefetch_operand(src)?.unwrap_integer()?;
+		let old = self.load_memory(address, MemoryFormat::WORD)?;
+		self.store_memory(address, MemoryFormat::WORD, action(old, value))?;
+		self.store_value(dst, OperandValue::IntegerValue(old))?;
+		self.opcode_pointer += 1;
+		Ok(())
+	}
+
+	/// Push return address and continue at the start of a function.
+	fn call_function<'v>(&mut self, current_func: &Function, next_func: &'v Function) -> Result<&'v Function, Error> {
+		let depth = self.return_stack.len() + 1;
+		if depth > self.config.max_call_depth || self.stack_pointer + next_func.stackframe_size > self.config.stack_size {
+			return Err(self.stack_overflow(depth, &[current_func.name, next_func.name]));
 		}
+		self.return_stack.push((current_func.name, self.opcode_pointer + 1, self.stack_pointer));
+		self.opcode_pointer = next_func.opcodes_range.start;
+		self.stack_pointer += next_func.stackframe_size;
+		Ok(next_func)
+	}
+
+	/// Chain of calls consists of callers saved in return stack and functions on top of it.
+	/// The bottom entry only marks the end of a task, its caller is not a real call.
+	fn stack_overflow(&self, depth: usize, top: &[&'static str]) -> Error {
+		let chain: Vec<&'static str> = self.return_stack.iter().skip(1).map(|ret| ret.0)
+				.chain(top.iter().cloned())
+				.collect();
+		Error::StackOverflow(depth, chain)
 	}
 
 	/// Generic implementation of all kinds of jumps.
+	/// Predicate receives result of arguments comparison, None means unordered floating values.
 	fn jump_generic<'x, F>(&mut self, current_func: &'x Function, label_name: &'static str,
 			predicate: F, arg1: OperandType, arg2: OperandType)
-			-> Result<(&'x Function), Error> where F: FnOnce(i64, i64) -> bool
+			-> Result<&'x Function, Error> where F: FnOnce(Option<Ordering>) -> bool
 	{
 		let jmp_dst: usize = *current_func.labels.get(label_name).ok_or(Error::LabelDoesNotExist(label_name))?;
 		if current_func.is_opcode_in_range(jmp_dst) {
-			let arg_val1: i64 = (self.prefetch_operand(arg1)?).unwrap_integer()?;
-			let arg_val2: i64 = (self.prefetch_operand(arg2)?).unwrap_integer()?;
-			if predicate(arg_val1, arg_val2) {
+			let arg_val1: OperandValue = self.prefetch_operand(arg1)?;
+			let arg_val2: OperandValue = self.prefetch_operand(arg2)?;
+			if predicate(arg_val1.compare(arg_val2)?) {
 				self.opcode_pointer = jmp_dst;
+			} else {
+				self.opcode_pointer += 1;
 			}
 			Ok(current_func)
 		} else {
@@ -65,6 +391,29 @@ impl ParallaxVm {
 		}
 	}
 
+	/// Convert integer operands, so that their signed comparison gives the same result as unsigned one.
+	/// Flipping the top bit maps 0..=u64::MAX onto i64::MIN..=i64::MAX keeping the order.
+	fn unsigned_operands(&self, arg1: OperandType, arg2: OperandType) -> Result<(OperandType, OperandType), Error> {
+		let val1: i64 = self.prefetch_operand(arg1)?.unwrap_integer()?;
+		let val2: i64 = self.prefetch_operand(arg2)?.unwrap_integer()?;
+		Ok((OperandType::IntegerConstant(val1 ^ i64::MIN), OperandType::IntegerConstant(val2 ^ i64::MIN)))
+	}
+
+	/// Generic implementation of all kinds of conditional moves: dst = src if predicate holds for arg1 and arg2.
+	/// dst is left untouched otherwise.
+	fn move_generic<F>(&mut self, predicate: F, dst: OperandType, src: OperandType, arg1: OperandType, arg2: OperandType)
+			-> Result<(), Error> where F: FnOnce(Option<Ordering>) -> bool
+	{
+		let arg_val1: OperandValue = self.prefetch_operand(arg1)?;
+		let arg_val2: OperandValue = self.prefetch_operand(arg2)?;
+		if predicate(arg_val1.compare(arg_val2)?) {
+			let src_val = self.prefetch_operand(src)?;
+			self.store_value(dst, src_val)?;
+		}
+		self.opcode_pointer += 1;
+		Ok(())
+	}
+
 	/// Performs an "action" on 2 arguments and stores the result into dst.
 	fn two_operand_action_generic<F>(&mut self, action: F, dst: OperandType, arg1: OperandType, arg2: OperandType)
 			-> Result<(), Error> where F: FnOnce(OperandValue, OperandValue) -> Result<OperandValue, Error> 
@@ -77,22 +426,81 @@ impl ParallaxVm {
 		self.store_value(dst, action(src_val1, src_val2)?)
 	}
 
+	/// Same as two_operand_action_generic, but integer values go to integer_action, that also sets flags.
+	/// Integer action receives current carry flag as a third argument.
+	fn flags_action_generic<F, G>(&mut self, integer_action: F, action: G,
+			dst: OperandType, arg1: OperandType, arg2: OperandType) -> Result<(), Error>
+			where F: FnOnce(i64, i64, bool) -> (i64, Flags),
+			      G: FnOnce(OperandValue, OperandValue) -> Result<OperandValue, Error>
+	{
+		let src_val1 = self.prefetch_operand(arg1)?;
+		let src_val2 = self.prefetch_operand(arg2)?;
+		self.opcode_pointer += 1;
+		let result = match (src_val1.unwrap_integer(), src_val2.unwrap_integer()) {
+			(Ok(x), Ok(y)) => {
+				let (value, flags) = integer_action(x, y, self.flags.carry);
+				self.flags = flags;
+				OperandValue::IntegerValue(value)
+			},
+			_ => action(src_val1, src_val2)?,
+		};
+		self.store_value(dst, result)
+	}
+
+	/// Performs an "action" on a single argument and stores the result into dst.
+	fn one_operand_action_generic<F>(&mut self, action: F, dst: OperandType, arg: OperandType)
+			-> Result<(), Error> where F: FnOnce(OperandValue) -> Result<OperandValue, Error>
+	{
+		let src_val = self.prefetch_operand(arg)?;
+		self.opcode_pointer += 1;
+		self.store_value(dst, action(src_val)?)
+	}
+
 	/// A single "turn" of a virtual machine, i.e. processing a single operation.
 	/// Returns reference to current executing Function and stack depth.
-	fn turn<'v>(&mut self, operation: &Opcode, current_func: &'v Function, functions: &'v HashMap<&'static str, Function>)
-			-> Result<(&'v Function), Error> {
+	fn turn<'v>(&mut self, operation: &Opcode, current_func: &'v Function, program: &'v Program)
+			-> Result<&'v Function, Error> {
+		let functions: &'v HashMap<&'static str, Function> = program.functions();
 		match *operation {
+			Opcode::Registers(..)        => {Err(Error::OpcodeMustBeUnreachable)},
+			Opcode::Data(..)             => {Err(Error::OpcodeMustBeUnreachable)},
+			Opcode::ReadOnlyData(..)     => {Err(Error::OpcodeMustBeUnreachable)},
 			Opcode::FunctionStart(_name) => {Err(Error::OpcodeMustBeUnreachable)},
 			Opcode::FunctionEnd          => {Err(Error::OpcodeMustBeUnreachable)},
 			Opcode::Call(name) => {
 				//println!("call {}", name);
 				let next_func: &'v Function = functions.get(name).ok_or(Error::FunctionIsNotDefined(name))?;
-				// TODO: Create some recursion monitor that can kill app before stack is exhausted?
-				self.return_stack.push((current_func.name, self.opcode_pointer + 1, self.stack_pointer));
+				self.call_function(current_func, next_func)
+			},
+			Opcode::TailCall(name) => {
+				//println!("tail_call {}", name);
+				let next_func: &'v Function = functions.get(name).ok_or(Error::FunctionIsNotDefined(name))?;
+				if !current_func.is_tail_position(self.opcode_pointer, program.opcodes()) {
+					return Err(Error::TailCallNotInTailPosition(current_func.name));
+				}
+				// Return address stays the same, only the frame size changes.
+				// Frame of an entry function is not counted, so stack pointer may be less than its size.
+				let stack_pointer = self.stack_pointer.saturating_sub(current_func.stackframe_size) + next_func.stackframe_size;
+				if stack_pointer > self.config.stack_size {
+					return Err(self.stack_overflow(self.return_stack.len(), &[next_func.name]));
+				}
+				self.stack_pointer = stack_pointer;
 				self.opcode_pointer = next_func.opcodes_range.start;
-				self.stack_pointer += next_func.stackframe_size;
 				Ok(next_func)
 			},
+			Opcode::CallIndirect(handle) => {
+				//println!("call_indirect");
+				let handle = self.prefetch_operand(handle)?.unwrap_integer()?;
+				let next_func: &'v Function = program.function_by_handle(handle).ok_or(Error::InvalidFunctionHandle(handle))?;
+				self.call_function(current_func, next_func)
+			},
+			Opcode::FuncRef(dst, name) => {
+				//println!("func_ref {}", name);
+				let handle = program.function_handle(name).ok_or(Error::FunctionIsNotDefined(name))?;
+				self.store_value(dst, OperandValue::IntegerValue(handle))?;
+				self.opcode_pointer += 1;
+				Ok(current_func)
+			},
 			Opcode::Return => {
 				//println!("return");
 				let ret: (&'static str, usize, usize) = self.return_stack.pop().ok_or(Error::ReturnStackExhausted)?;
@@ -105,6 +513,12 @@ impl ParallaxVm {
 				Ok(previous_func)
 			},
 			//=================================================================================================
+			Opcode::Signature(_arguments, _results) => {
+				//println!("signature");
+				// Same as label, used on Function init only.
+				self.opcode_pointer += 1;
+				Ok(current_func)
+			},
 			Opcode::Label(_name) => {
 				//println("label {}", _name);
 				//Actually we do nothing. Label is a service opcode, needed on Function init.
@@ -113,40 +527,114 @@ impl ParallaxVm {
 			},
 			Opcode::Jump(name) => {
 				//println("jump to {} label", name);
-				self.jump_generic(current_func, name, |_, _| true,
+				self.jump_generic(current_func, name, |_| true,
 						OperandType::IntegerConstant(0), OperandType::IntegerConstant(0))
 			},
 			Opcode::JumpZero(name, arg1) => {
 				//println("jump_zero to {} label", name);
-				self.jump_generic(current_func, name, |x, _| x == 0, arg1, OperandType::IntegerConstant(0))
+				self.jump_generic(current_func, name, is_equal, arg1, arg1.zero_like())
 			},
 			Opcode::JumpNotZero(name, arg1) => {
 				//println("jump_not_zero to {} label", name);
-				self.jump_generic(current_func, name, |x, _| x != 0, arg1, OperandType::IntegerConstant(0))
+				self.jump_generic(current_func, name, is_not_equal, arg1, arg1.zero_like())
 			},
 			Opcode::JumpBelow(name, arg1, arg2) => {
 				//println("jump_below to {} label", name);
-				self.jump_generic(current_func, name, |x, y| x < y, arg1, arg2)
+				self.jump_generic(current_func, name, is_below, arg1, arg2)
 			},
 			Opcode::JumpBelowEqual(name, arg1, arg2) => {
 				//println("jump_below_eq to {} label", name);
-				self.jump_generic(current_func, name, |x, y| x <= y, arg1, arg2)
+				self.jump_generic(current_func, name, is_below_equal, arg1, arg2)
 			},
 			Opcode::JumpAbove(name, arg1, arg2) => {
 				//println("jump_above to {} label", name);
-				self.jump_generic(current_func, name, |x, y| x > y, arg1, arg2)
+				self.jump_generic(current_func, name, is_above, arg1, arg2)
 			},
 			Opcode::JumpAboveEqual(name, arg1, arg2) => {
 				//println("jump_above_eq to {} label", name);
-				self.jump_generic(current_func, name, |x, y| x >= y, arg1, arg2)
+				self.jump_generic(current_func, name, is_above_equal, arg1, arg2)
 			},
 			Opcode::JumpEqual(name, arg1, arg2) => {
 				//println("jump_equal to {} label", name);
-				self.jump_generic(current_func, name, |x, y| x == y, arg1, arg2)
+				self.jump_generic(current_func, name, is_equal, arg1, arg2)
 			},
 			Opcode::JumpNotEqual(name, arg1, arg2) => {
 				//println("jump_not_equal to {} label", name);
-				self.jump_generic(current_func, name, |x, y| x != y, arg1, arg2)
+				self.jump_generic(current_func, name, is_not_equal, arg1, arg2)
+			},
+			Opcode::JumpBelowUnsigned(name, arg1, arg2) => {
+				//println("jump_below_unsigned to {} label", name);
+				let (arg1, arg2) = self.unsigned_operands(arg1, arg2)?;
+				self.jump_generic(current_func, name, is_below, arg1, arg2)
+			},
+			Opcode::JumpBelowEqualUnsigned(name, arg1, arg2) => {
+				//println("jump_below_equal_unsigned to {} label", name);
+				let (arg1, arg2) = self.unsigned_operands(arg1, arg2)?;
+				self.jump_generic(current_func, name, is_below_equal, arg1, arg2)
+			},
+			Opcode::JumpAboveUnsigned(name, arg1, arg2) => {
+				//println("jump_above_unsigned to {} label", name);
+				let (arg1, arg2) = self.unsigned_operands(arg1, arg2)?;
+				self.jump_generic(current_func, name, is_above, arg1, arg2)
+			},
+			Opcode::JumpAboveEqualUnsigned(name, arg1, arg2) => {
+				//println("jump_above_equal_unsigned to {} label", name);
+				let (arg1, arg2) = self.unsigned_operands(arg1, arg2)?;
+				self.jump_generic(current_func, name, is_above_equal, arg1, arg2)
+			},
+			Opcode::JumpUnordered(name, arg1, arg2) => {
+				//println("jump_unordered to {} label", name);
+				self.jump_generic(current_func, name, is_unordered, arg1, arg2)
+			},
+			Opcode::Compare(arg1, arg2) => {
+				//println("compare");
+				let val1 = self.prefetch_operand(arg1)?.unwrap_integer()?;
+				let val2 = self.prefetch_operand(arg2)?.unwrap_integer()?;
+				self.flags = Flags::sub(val1, val2, false).1;
+				self.opcode_pointer += 1;
+				Ok(current_func)
+			},
+			Opcode::JumpIf(condition, name) => {
+				//println("jump_if {:?} to {} label", condition, name);
+				let flags = self.flags;
+				self.jump_generic(current_func, name, |_| flags.check(condition),
+						OperandType::IntegerConstant(0), OperandType::IntegerConstant(0))
+			},
+			Opcode::Switch(index, _labels, _default) => {
+				//println("switch");
+				// Labels are resolved into the jump table when the function is defined.
+				let &(ref table, default) = current_func.switch_tables.get(&self.opcode_pointer)
+						.ok_or(Error::RestrictedJumpOutOfScope(current_func.name))?;
+				let index: i64 = self.prefetch_operand(index)?.unwrap_integer()?;
+				let jmp_dst: usize = if index < 0 {
+					default
+				} else {
+					table.get(index as usize).cloned().unwrap_or(default)
+				};
+				if current_func.is_opcode_in_range(jmp_dst) {
+					self.opcode_pointer = jmp_dst;
+					Ok(current_func)
+				} else {
+					// Should never ever happen, labels are collected inside of the function.
+					Err(Error::RestrictedJumpOutOfScope(current_func.name))
+				}
+			},
+			Opcode::Protect(..) => {
+				//println!("protect");
+				// Same as label, used on Function init only.
+				self.opcode_pointer += 1;
+				Ok(current_func)
+			},
+			Opcode::Throw(code) => {
+				//println!("throw");
+				let code = self.prefetch_operand(code)?.unwrap_integer()?;
+				Err(Error::UncaughtException(code))
+			},
+			Opcode::Catch(dst) => {
+				//println!("catch");
+				self.store_value(dst, OperandValue::IntegerValue(self.exception_code))?;
+				self.opcode_pointer += 1;
+				Ok(current_func)
 			},
 			//=================================================================================================
 			Opcode::Move(dst, src) => {
@@ -156,109 +644,766 @@ impl ParallaxVm {
 				self.opcode_pointer += 1;
 				Ok(current_func)
 			},
+			Opcode::Load(dst, address, format) => {
+				//println!("load");
+				let address = self.memory_address(address)?;
+				let value = self.load_memory(address, format)?;
+				self.store_value(dst, OperandValue::IntegerValue(value))?;
+				self.opcode_pointer += 1;
+				Ok(current_func)
+			},
+			Opcode::Store(address, src, format) => {
+				//println!("store");
+				let address = self.memory_address(address)?;
+				let value = self.prefetch_operand(src)?.unwrap_integer()?;
+				self.store_memory(address, format, value)?;
+				self.opcode_pointer += 1;
+				Ok(current_func)
+			},
+			Opcode::Select(dst, condition, src1, src2) => {
+				//println!("select");
+				let condition_val = self.prefetch_operand(condition)?;
+				let zero = self.prefetch_operand(condition.zero_like())?;
+				let selected = if is_not_equal(condition_val.compare(zero)?) { src1 } else { src2 };
+				let src_val = self.prefetch_operand(selected)?;
+				self.store_value(dst, src_val)?;
+				self.opcode_pointer += 1;
+				Ok(current_func)
+			},
+			Opcode::MoveIfBelow(dst, src, arg1, arg2) => {
+				self.move_generic(is_below, dst, src, arg1, arg2)?;
+				Ok(current_func)
+			},
+			Opcode::MoveIfBelowEqual(dst, src, arg1, arg2) => {
+				self.move_generic(is_below_equal, dst, src, arg1, arg2)?;
+				Ok(current_func)
+			},
+			Opcode::MoveIfAbove(dst, src, arg1, arg2) => {
+				self.move_generic(is_above, dst, src, arg1, arg2)?;
+				Ok(current_func)
+			},
+			Opcode::MoveIfAboveEqual(dst, src, arg1, arg2) => {
+				self.move_generic(is_above_equal, dst, src, arg1, arg2)?;
+				Ok(current_func)
+			},
+			Opcode::MoveIfEqual(dst, src, arg1, arg2) => {
+				self.move_generic(is_equal, dst, src, arg1, arg2)?;
+				Ok(current_func)
+			},
+			Opcode::MoveIfNotEqual(dst, src, arg1, arg2) => {
+				self.move_generic(is_not_equal, dst, src, arg1, arg2)?;
+				Ok(current_func)
+			},
+			Opcode::MoveIfUnordered(dst, src, arg1, arg2) => {
+				self.move_generic(is_unordered, dst, src, arg1, arg2)?;
+				Ok(current_func)
+			},
 			Opcode::Add(dst, src1, src2) => {
 				//println!("add");
-				self.two_operand_action_generic(|x, y| x + y, dst, src1, src2)?;
+				self.flags_action_generic(|x, y, _| Flags::add(x, y, false), |x, y| x + y, dst, src1, src2)?;
 				Ok(current_func)
 			},
 			Opcode::Sub(dst, src1, src2) => {
 				//println!("subtract");
-				self.two_operand_action_generic(|x, y| x - y, dst, src1, src2)?;
+				self.flags_action_generic(|x, y, _| Flags::sub(x, y, false), |x, y| x - y, dst, src1, src2)?;
 				Ok(current_func)
 			},
-		}
-	}
-
-	/// Executes given operations on the machine.
-	/// Returns number of seconds spent on execution.
-	pub fn run(&mut self, program: &[Opcode]) -> Result<u64, Error> {
-		let start_time = Instant::now();
-
-		// Functions Map is a result of parsing and it is external to VM
-		// (came as list of opcodes), so I don't put them in VM's structure.
-		let mut functions: HashMap<&'static str, Function> = HashMap::new();
-
-		// Collect all available functions
-		for (i, &op) in program.iter().enumerate() {
-			if let Opcode::FunctionStart(name) = op {
-				// TODO: change to Error
-				assert!(!functions.contains_key(name), "Function {} was already defined before!", name);
-				let current_func: Function = self.define_function(name, i, &program[i..])?;
-				functions.insert(name, current_func);
-			}
-		}
-
-		// Start from entry point - "main" function
-		let main_func_name: &'static str = "main";
-		let mut current_function: &Function = functions
-				.get(main_func_name)
-				.ok_or(Error::FunctionIsNotDefined(main_func_name))?;
-
-		{
-			// Init stack (empty right now), first opcode to start with and return address.
-			// TODO: is it OK to write last main's opcode address as a return address?
-			self.stack_pointer = 0;
-			self.opcode_pointer = current_function.opcodes_range.start;
-			self.return_stack.push((main_func_name, current_function.opcodes_range.end, self.stack_pointer));
-		}
-
-		while !self.return_stack.is_empty() {
-			// Redundant check that should never fail.
-			if current_function.is_opcode_in_range(self.opcode_pointer) {
-				let current_opcode: &Opcode = &program[self.opcode_pointer];
-				current_function = self.turn(current_opcode, current_function, &functions)?;
-			} else {
-				panic!("All safety measures failed. Running opcode is out of current function. Aborting...");
-			}
-		}
-
-		// TODO: change to u128 and milliseconds when it becomes stable.
-		let elapsed = start_time.elapsed().as_secs();
-		Ok(elapsed)
-	}
-
-	/// Define a new function and store it in VM for future use.
-	/// index - index of FunctionStart opcode in a whole program
-	/// program - SLICE of program starting from index!
-	pub fn define_function(&self, fname: &'static str, index: usize, program: &[Opcode]) -> Result<Function, Error> {
-		let mut function_result: Function = Function{
-			name: fname,
-			opcodes_range: Range{start: (index + 1), end: 0},
-			stackframe_size: 10,
-			labels: HashMap::new(),
-		};
-		if let Opcode::FunctionStart(_name) = program[0] {
-			let func_end_disc = discriminant(&Opcode::FunctionEnd);
-			let func_end_index_o: Option<usize> = program.iter().position(|&x| discriminant(&x) == func_end_disc);
-
-			if let Some(func_end_index) = func_end_index_o {
-				// Mark where function ends
-				function_result.opcodes_range.end = index + func_end_index;
-
-				// Collect offsets of all labels.
-				for (i, opcode) in program.iter().enumerate().take(func_end_index).skip(1)  {
-					if let Opcode::Label(label_name) = opcode {
-						// Label offset = global offset (index) + local offset (i)
-						function_result.labels.insert(label_name, index + i);
-						continue;
-					}
-					if let Opcode::FunctionStart(func_name) = opcode {
-						// Did you try to define a nested function?
-						return Err(Error::BrokenFunctionDefinition(func_name));
-					}
+			Opcode::AddCarry(dst, src1, src2) => {
+				//println!("add with carry");
+				self.flags_action_generic(Flags::add, |_, _| Err(Error::UnsupportedOperand), dst, src1, src2)?;
+				Ok(current_func)
+			},
+			Opcode::SubBorrow(dst, src1, src2) => {
+				//println!("subtract with borrow");
+				self.flags_action_generic(Flags::sub, |_, _| Err(Error::UnsupportedOperand), dst, src1, src2)?;
+				Ok(current_func)
+			},
+			Opcode::Mul(dst, src1, src2) => {
+				//println!("multiply");
+				self.flags_action_generic(|x, y, _| Flags::mul(x, y), |x, y| x * y, dst, src1, src2)?;
+				Ok(current_func)
+			},
+			Opcode::Div(dst, src1, src2) => {
+				//println!("divide");
+				self.two_operand_action_generic(|x, y| x / y, dst, src1, src2)?;
+				Ok(current_func)
+			},
+			Opcode::Abs(dst, src) => {
+				self.one_operand_action_generic(|x| x.abs(), dst, src)?;
+				Ok(current_func)
+			},
+			Opcode::Neg(dst, src) => {
+				self.one_operand_action_generic(|x| -x, dst, src)?;
+				Ok(current_func)
+			},
+			Opcode::Min(dst, src1, src2) => {
+				self.two_operand_action_generic(|x, y| x.min(y), dst, src1, src2)?;
+				Ok(current_func)
+			},
+			Opcode::Max(dst, src1, src2) => {
+				self.two_operand_action_generic(|x, y| x.max(y), dst, src1, src2)?;
+				Ok(current_func)
+			},
+			//=================================================================================================
+			#[cfg(feature = "float")]
+			Opcode::Sqrt(dst, src) => {
+				self.one_operand_action_generic(|x| x.map_floating(f64::sqrt), dst, src)?;
+				Ok(current_func)
+			},
+			#[cfg(feature = "float")]
+			Opcode::Floor(dst, src) => {
+				self.one_operand_action_generic(|x| x.map_floating(f64::floor), dst, src)?;
+				Ok(current_func)
+			},
+			#[cfg(feature = "float")]
+			Opcode::Ceil(dst, src) => {
+				self.one_operand_action_generic(|x| x.map_floating(f64::ceil), dst, src)?;
+				Ok(current_func)
+			},
+			#[cfg(feature = "float")]
+			Opcode::Round(dst, src) => {
+				self.one_operand_action_generic(|x| x.map_floating(f64::round), dst, src)?;
+				Ok(current_func)
+			},
+			#[cfg(feature = "float")]
+			Opcode::FusedMulAdd(dst, src1, src2, src3) => {
+				let addend: f64 = self.prefetch_operand(src3)?.unwrap_floating()?;
+				self.two_operand_action_generic(|x, y| {
+					let factor: f64 = y.unwrap_floating()?;
+					x.map_floating(|x| x.mul_add(factor, addend))
+				}, dst, src1, src2)?;
+				Ok(current_func)
+			},
+			#[cfg(feature = "float")]
+			Opcode::Sin(dst, src) => {
+				self.one_operand_action_generic(|x| x.map_floating(f64::sin), dst, src)?;
+				Ok(current_func)
+			},
+			#[cfg(feature = "float")]
+			Opcode::Cos(dst, src) => {
+				self.one_operand_action_generic(|x| x.map_floating(f64::cos), dst, src)?;
+				Ok(current_func)
+			},
+			#[cfg(feature = "float")]
+			Opcode::Exp(dst, src) => {
+				self.one_operand_action_generic(|x| x.map_floating(f64::exp), dst, src)?;
+				Ok(current_func)
+			},
+			#[cfg(feature = "float")]
+			Opcode::Ln(dst, src) => {
+				self.one_operand_action_generic(|x| x.map_floating(f64::ln), dst, src)?;
+				Ok(current_func)
+			},
+			#[cfg(feature = "float")]
+			Opcode::Pow(dst, src1, src2) => {
+				self.two_operand_action_generic(|x, y| {
+					let exponent: f64 = y.unwrap_floating()?;
+					x.map_floating(|x| x.powf(exponent))
+				}, dst, src1, src2)?;
+				Ok(current_func)
+			},
+			//=================================================================================================
+			Opcode::Alloc(dst, size) => {
+				//println!("alloc");
+				let size = self.prefetch_operand(size)?.unwrap_integer()?;
+				let address = self.heap.alloc(&mut self.memory, heap_size(size)?)?;
+				self.store_value(dst, OperandValue::IntegerValue(address as i64))?;
+				self.opcode_pointer += 1;
+				Ok(current_func)
+			},
+			Opcode::Free(ptr) => {
+				//println!("free");
+				let address = self.prefetch_operand(ptr)?.unwrap_integer()?;
+				self.heap.free(address as usize)?;
+				self.opcode_pointer += 1;
+				Ok(current_func)
+			},
+			Opcode::Realloc(dst, ptr, size) => {
+				//println!("realloc");
+				let address = self.prefetch_operand(ptr)?.unwrap_integer()?;
+				let size = self.prefetch_operand(size)?.unwrap_integer()?;
+				let address = self.heap.realloc(&mut self.memory, address as usize, heap_size(size)?)?;
+				self.store_value(dst, OperandValue::IntegerValue(address as i64))?;
+				self.opcode_pointer += 1;
+				Ok(current_func)
+			},
+			//=================================================================================================
+			Opcode::Spawn(dst, name, args) => {
+				//println!("spawn {}", name);
+				let function: &'v Function = functions.get(name).ok_or(Error::FunctionIsNotDefined(name))?;
+				let context = self.new_context(function, args)?;
+				self.tasks.push(Task {
+					state: TaskState::Ready,
+					entry: name,
+					context,
+					resumers: Vec::new(),
+				});
+				self.store_value(dst, OperandValue::IntegerValue(self.tasks.len() as i64 - 1))?;
+				self.opcode_pointer += 1;
+				Ok(current_func)
+			},
+			Opcode::Yield => {
+				//println!("yield");
+				self.task_switch = true;
+				self.opcode_pointer += 1;
+				Ok(current_func)
+			},
+			Opcode::Join(handle) => {
+				//println!("join");
+				let handle = self.prefetch_operand(handle)?.unwrap_integer()?;
+				let task: &Task = self.tasks.get(handle as usize).ok_or(Error::NoSuchTask(handle))?;
+				if task.state != TaskState::Finished {
+					self.block_task(TaskState::Joining(handle as usize));
+					return Ok(current_func);
 				}
-			} else {
-				return Err(Error::BrokenFunctionDefinition(fname));
-			}
-		} else {
-			return Err(Error::BrokenFunctionDefinition(fname));
-		}
-
-		Ok(function_result)
-	}
-
-	/// Get value from integer_register or returns an error
-	fn get_int_register(&self, reg_number: usize) -> Result<i64, Error> {
+				let entry: &Function = functions.get(task.entry).ok_or(Error::FunctionIsNotDefined(task.entry))?;
+				let registers: &[i64] = &task.context.integer_register;
+				if entry.results > registers.len() {
+					return Err(Error::NoSuchIntegerRegister(registers.len(), entry.results - 1));
+				}
+				let results: Vec<i64> = registers[..entry.results].to_vec();
+				self.integer_register[..results.len()].copy_from_slice(&results);
+				self.opcode_pointer += 1;
+				Ok(current_func)
+			},
+			Opcode::CoroutineNew(dst, name, args) => {
+				//println!("coroutine_new {}", name);
+				let function: &'v Function = functions.get(name).ok_or(Error::FunctionIsNotDefined(name))?;
+				let context = self.new_context(function, args)?;
+				self.coroutines.push(Coroutine{state: CoroutineState::Suspended, context});
+				self.store_value(dst, OperandValue::IntegerValue(self.coroutines.len() as i64 - 1))?;
+				self.opcode_pointer += 1;
+				Ok(current_func)
+			},
+			Opcode::Resume(status, dst, handle) => {
+				//println!("resume");
+				let handle = self.prefetch_operand(handle)?.unwrap_integer()?;
+				let coroutine: &mut Coroutine = self.coroutines.get_mut(handle as usize).ok_or(Error::NoSuchCoroutine(handle))?;
+				if coroutine.state != CoroutineState::Suspended {
+					return Err(Error::CoroutineIsNotSuspended(handle as usize));
+				}
+				coroutine.state = CoroutineState::Running;
+				let context = mem::take(&mut coroutine.context);
+				let resumer = self.save_context(current_func.name);
+				self.resumers.push(Resumer{coroutine: handle as usize, status, dst, context: resumer});
+				let name = self.load_context(context);
+				functions.get(name).ok_or(Error::FunctionIsNotDefined(name))
+			},
+			Opcode::YieldValue(src) => {
+				//println!("yield_value");
+				let value = self.prefetch_operand(src)?;
+				let resumer: Resumer = self.resumers.pop().ok_or(Error::YieldOutsideCoroutine)?;
+				self.opcode_pointer += 1;
+				let context = self.save_context(current_func.name);
+				let coroutine: &mut Coroutine = &mut self.coroutines[resumer.coroutine];
+				coroutine.state = CoroutineState::Suspended;
+				coroutine.context = context;
+				self.return_to_resumer(resumer, Some(value), functions)
+			},
+			Opcode::ChanNew(dst, capacity) => {
+				//println!("chan_new");
+				let capacity = self.prefetch_operand(capacity)?.unwrap_integer()?;
+				if capacity < 0 {
+					return Err(Error::UnsupportedOperand);
+				}
+				self.channels.push(Channel::new(capacity as usize));
+				self.store_value(dst, OperandValue::IntegerValue(self.channels.len() as i64 - 1))?;
+				self.opcode_pointer += 1;
+				Ok(current_func)
+			},
+			Opcode::Send(channel, src) => {
+				//println!("send");
+				let handle = self.prefetch_operand(channel)?.unwrap_integer()?;
+				let value = self.prefetch_operand(src)?.unwrap_integer()?;
+				match self.get_channel(handle)?.try_send(value) {
+					Ok(()) => self.opcode_pointer += 1,
+					Err(ChannelError::WouldBlock) => self.block_task(TaskState::Sending(handle as usize)),
+					Err(ChannelError::Closed) => return Err(Error::ChannelClosed(handle as usize)),
+				}
+				Ok(current_func)
+			},
+			Opcode::Recv(dst, channel) => {
+				//println!("recv");
+				let handle = self.prefetch_operand(channel)?.unwrap_integer()?;
+				match self.get_channel(handle)?.try_recv() {
+					Ok(value) => {
+						self.store_value(dst, OperandValue::IntegerValue(value))?;
+						self.opcode_pointer += 1;
+					},
+					Err(ChannelError::WouldBlock) => self.block_task(TaskState::Receiving(handle as usize)),
+					Err(ChannelError::Closed) => return Err(Error::ChannelClosed(handle as usize)),
+				}
+				Ok(current_func)
+			},
+			Opcode::TrySend(status, channel, src) => {
+				//println!("try_send");
+				let handle = self.prefetch_operand(channel)?.unwrap_integer()?;
+				let value = self.prefetch_operand(src)?.unwrap_integer()?;
+				let result = self.get_channel(handle)?.try_send(value);
+				self.store_value(status, OperandValue::IntegerValue(channel_status(result)))?;
+				self.opcode_pointer += 1;
+				Ok(current_func)
+			},
+			Opcode::TryRecv(status, dst, channel) => {
+				//println!("try_recv");
+				let handle = self.prefetch_operand(channel)?.unwrap_integer()?;
+				let result = self.get_channel(handle)?.try_recv();
+				if let Ok(value) = result {
+					self.store_value(dst, OperandValue::IntegerValue(value))?;
+				}
+				self.store_value(status, OperandValue::IntegerValue(channel_status(result.map(|_| ()))))?;
+				self.opcode_pointer += 1;
+				Ok(current_func)
+			},
+			Opcode::Close(channel) => {
+				//println!("close");
+				let handle = self.prefetch_operand(channel)?.unwrap_integer()?;
+				self.get_channel(handle)?.close().map_err(|_| Error::ChannelClosed(handle as usize))?;
+				self.opcode_pointer += 1;
+				Ok(current_func)
+			},
+			//=================================================================================================
+			// Tasks switch only between opcodes, so every opcode is atomic.
+			Opcode::AtomicLoad(dst, address) => {
+				//println!("atomic_load");
+				let address = self.atomic_address(address)?;
+				let value = self.load_memory(address, MemoryFormat::WORD)?;
+				self.store_value(dst, OperandValue::IntegerValue(value))?;
+				self.opcode_pointer += 1;
+				Ok(current_func)
+			},
+			Opcode::AtomicStore(address, src) => {
+				//println!("atomic_store");
+				let address = self.atomic_address(address)?;
+				let value = self.prefetch_operand(src)?.unwrap_integer()?;
+				self.store_memory(address, MemoryFormat::WORD, value)?;
+				self.opcode_pointer += 1;
+				Ok(current_func)
+			},
+			Opcode::CompareExchange(dst, address, expected, new) => {
+				//println!("compare_exchange");
+				let address = self.atomic_address(address)?;
+				let expected = self.prefetch_operand(expected)?.unwrap_integer()?;
+				let new = self.prefetch_operand(new)?.unwrap_integer()?;
+				let old = self.load_memory(address, MemoryFormat::WORD)?;
+				if old == expected {
+					self.store_memory(address, MemoryFormat::WORD, new)?;
+				}
+				self.flags = Flags::sub(old, expected, false).1;
+				self.store_value(dst, OperandValue::IntegerValue(old))?;
+				self.opcode_pointer += 1;
+				Ok(current_func)
+			},
+			Opcode::FetchAdd(dst, address, src) => {
+				//println!("fetch_add");
+				self.fetch_generic(|x, y| x.wrapping_add(y), dst, address, src)?;
+				Ok(current_func)
+			},
+			Opcode::FetchAnd(dst, address, src) => {
+				//println!("fetch_and");
+				self.fetch_generic(|x, y| x & y, dst, address, src)?;
+				Ok(current_func)
+			},
+			Opcode::FetchOr(dst, address, src) => {
+				//println!("fetch_or");
+				self.fetch_generic(|x, y| x | y, dst, address, src)?;
+				Ok(current_func)
+			},
+			Opcode::FetchXor(dst, address, src) => {
+				//println!("fetch_xor");
+				self.fetch_generic(|x, y| x ^ y, dst, address, src)?;
+				Ok(current_func)
+			},
+			Opcode::Fence => {
+				//println!("fence");
+				self.opcode_pointer += 1;
+				Ok(current_func)
+			},
+			//=================================================================================================
+			Opcode::PrintInteger(src) => {
+				//println!("print_integer");
+				let src_val = self.prefetch_operand(src)?.unwrap_integer()?;
+				self.io.print_integer(src_val)?;
+				self.opcode_pointer += 1;
+				Ok(current_func)
+			},
+			Opcode::PrintString(text) => {
+				//println!("print_string");
+				self.io.print_string(text)?;
+				self.opcode_pointer += 1;
+				Ok(current_func)
+			},
+			Opcode::ReadInteger(dst) => {
+				//println!("read_integer");
+				let new_value = self.io.read_integer()?;
+				self.store_value(dst, OperandValue::IntegerValue(new_value))?;
+				self.opcode_pointer += 1;
+				Ok(current_func)
+			},
+		}
+	}
+
+	/// Executes given operations on the machine, starting from "main" function.
+	/// Returns number of seconds spent on execution.
+	pub fn run(&mut self, program: &[Opcode]) -> Result<u64, Error> {
+		// Functions Map is a result of parsing and it is external to VM
+		// (came as list of opcodes), so I don't put them in VM's structure.
+		let program = Program::new(program)?;
+		self.run_program(&program, "main")
+	}
+
+	/// Executes already loaded program starting from a given entry function.
+	/// Returns number of seconds spent on execution.
+	pub fn run_program(&mut self, program: &Program, entry: &'static str) -> Result<u64, Error> {
+		let start_time = Instant::now();
+		self.prepare();
+		self.load_data(program)?;
+		self.execute(program, entry)?;
+		// TODO: change to u128 and milliseconds when it becomes stable.
+		let elapsed = start_time.elapsed().as_secs();
+		Ok(elapsed)
+	}
+
+	/// Calls a single function of a program with given arguments and returns its results.
+	/// Arguments are passed in r0, r1, ... and results are taken from the same registers,
+	/// their numbers are declared by Opcode::Signature.
+	pub fn run_function(&mut self, program: &Program, name: &'static str, args: &[i64]) -> Result<Vec<i64>, Error> {
+		let function: &Function = program.functions().get(name).ok_or(Error::FunctionIsNotDefined(name))?;
+		if args.len() != function.arguments {
+			return Err(Error::WrongNumberOfArguments(name, function.arguments, args.len()));
+		}
+		self.prepare();
+		self.load_data(program)?;
+		for (i, &arg) in args.iter().enumerate() {
+			self.set_int_register(i, arg)?;
+		}
+		self.execute(program, name)?;
+		(0..function.results).map(|i| self.get_int_register(i)).collect()
+	}
+
+	/// Clear registers, flags and memory, as well as call stack, pointers, tasks, channels,
+	/// coroutines and exception code of the last run.
+	/// Heap bookkeeping and fuel are not touched: they start anew in every run anyway.
+	/// Settings like fuel limit, run options and input/output are kept.
+	pub fn reset(&mut self) {
+		self.clear_registers();
+		self.memory.clear();
+		self.clear_call_stack();
+	}
+
+	/// Zero registers and flags.
+	fn clear_registers(&mut self) {
+		for value in self.integer_register.iter_mut() {
+			*value = 0;
+		}
+		#[cfg(feature = "float")]
+		for value in self.floating_register.iter_mut() {
+			*value = 0.0;
+		}
+		self.flags = Flags::default();
+	}
+
+	/// Set what is kept between runs.
+	pub fn set_run_options(&mut self, options: RunOptions) {
+		self.run_options = options;
+	}
+
+	/// Apply run options before a new run.
+	fn prepare(&mut self) {
+		if self.run_options.clear_registers {
+			self.clear_registers();
+		}
+		if self.run_options.clear_memory {
+			self.memory.clear();
+		}
+		self.clear_call_stack();
+	}
+
+	/// Put program data into memory. Data is restored before every run,
+	/// even if a previous run changed it. Heap starts empty right after data.
+	fn load_data(&mut self, program: &Program) -> Result<(), Error> {
+		program.check_config(&self.config)?;
+		self.heap = Heap::new(program.data_size(), self.config.memory_size, self.config.debug_heap);
+		for block in program.data() {
+			self.memory.write_bytes(block.address, &block.bytes)?;
+		}
+		self.memory.set_read_only(program.data().iter()
+				.filter(|block| block.read_only)
+				.map(|block| block.address..block.address + block.bytes.len())
+				.collect());
+		Ok(())
+	}
+
+	fn clear_call_stack(&mut self) {
+		self.opcode_pointer = 0;
+		self.stack_pointer = 0;
+		self.return_stack.clear();
+		self.tasks.clear();
+		self.current_task = 0;
+		self.task_switch = false;
+		self.channels.clear();
+		self.coroutines.clear();
+		self.resumers.clear();
+		self.exception_code = 0;
+	}
+
+	/// Unwind the call stack to the nearest handler of an exception raised at a given opcode.
+	/// Errors that are not exceptions are returned as is, as well as uncaught exceptions.
+	fn raise<'v>(&mut self, error: Error, opcode_pointer: usize, current_func: &'v Function,
+			functions: &'v HashMap<&'static str, Function>) -> Result<&'v Function, Error> {
+		let code: i64 = match error {
+			Error::UncaughtException(code) => code,
+			_ if error.is_trap() => error.exit_code() as i64,
+			_ => return Err(error),
+		};
+		// Call stack is already unwound, e.g. an exception after return to a resumer was not caught.
+		if self.return_stack.is_empty() {
+			return Err(error);
+		}
+		let mut function: &'v Function = current_func;
+		let mut pointer = opcode_pointer;
+		loop {
+			if let Some(handler) = function.find_handler(pointer) {
+				self.opcode_pointer = handler;
+				self.exception_code = code;
+				return Ok(function);
+			}
+			match self.return_stack.pop() {
+				// The last entry is the return address of a task or a coroutine, not a call.
+				Some(ret) if !self.return_stack.is_empty() => {
+					function = functions.get(ret.0).ok_or(Error::FunctionIsNotDefined(ret.0))?;
+					pointer = ret.1 - 1;
+					self.stack_pointer = ret.2;
+				},
+				_ => {
+					let resumer: Resumer = match self.resumers.pop() {
+						Some(resumer) => resumer,
+						None => return Err(error),
+					};
+					self.coroutines[resumer.coroutine].state = CoroutineState::Finished;
+					let name = self.load_context(resumer.context);
+					function = functions.get(name).ok_or(Error::FunctionIsNotDefined(name))?;
+					pointer = self.opcode_pointer;
+				},
+			}
+		}
+	}
+
+	/// Context to start a function in, arguments are copied into r0, r1, ...
+	fn new_context(&self, function: &Function, args: &[OperandType]) -> Result<Context, Error> {
+		if args.len() != function.arguments {
+			return Err(Error::WrongNumberOfArguments(function.name, function.arguments, args.len()));
+		}
+		let mut integer_register = vec![0; self.integer_register.len()];
+		if args.len() > integer_register.len() {
+			return Err(Error::NoSuchIntegerRegister(integer_register.len(), args.len() - 1));
+		}
+		for (i, &arg) in args.iter().enumerate() {
+			integer_register[i] = self.prefetch_operand(arg)?.unwrap_integer()?;
+		}
+		Ok(Context {
+			function: function.name,
+			integer_register,
+			#[cfg(feature = "float")]
+			floating_register: vec![0.0; self.floating_register.len()],
+			flags: Flags::default(),
+			opcode_pointer: function.opcodes_range.start,
+			stack_pointer: 0,
+			return_stack: vec![(function.name, function.opcodes_range.end, 0)],
+			exception_code: 0,
+		})
+	}
+
+	/// Move context of the running code out of the VM.
+	fn save_context(&mut self, function: &'static str) -> Context {
+		Context {
+			function,
+			integer_register: mem::take(&mut self.integer_register),
+			#[cfg(feature = "float")]
+			floating_register: mem::take(&mut self.floating_register),
+			flags: self.flags,
+			opcode_pointer: self.opcode_pointer,
+			stack_pointer: self.stack_pointer,
+			return_stack: mem::take(&mut self.return_stack),
+			exception_code: self.exception_code,
+		}
+	}
+
+	/// Make a context running. Returns name of the function to continue.
+	fn load_context(&mut self, context: Context) -> &'static str {
+		self.integer_register = context.integer_register;
+		#[cfg(feature = "float")]
+		{
+			self.floating_register = context.floating_register;
+		}
+		self.flags = context.flags;
+		self.opcode_pointer = context.opcode_pointer;
+		self.stack_pointer = context.stack_pointer;
+		self.return_stack = context.return_stack;
+		self.exception_code = context.exception_code;
+		context.function
+	}
+
+	/// Continue after Resume. Status is 1 if the coroutine yielded a value, 0 if it returned.
+	/// Failed store of results is raised at Resume, the resumer is already running.
+	fn return_to_resumer<'v>(&mut self, resumer: Resumer, value: Option<OperandValue>,
+			functions: &'v HashMap<&'static str, Function>) -> Result<&'v Function, Error> {
+		let (status, dst) = (resumer.status, resumer.dst);
+		let name = self.load_context(resumer.context);
+		let function: &'v Function = functions.get(name).ok_or(Error::FunctionIsNotDefined(name))?;
+		let stored = self.store_value(status, OperandValue::IntegerValue(value.is_some() as i64))
+				.and_then(|_| match value {
+					Some(value) => self.store_value(dst, value),
+					None => Ok(()),
+				});
+		match stored {
+			Ok(()) => {
+				self.opcode_pointer += 1;
+				Ok(function)
+			},
+			Err(error) => {
+				let pointer = self.opcode_pointer;
+				self.raise(error, pointer, function, functions)
+			},
+		}
+	}
+
+	/// Pick the next task after the current one, that can run.
+	/// The current task is picked last, so a yielding task runs again only if it is alone.
+	fn switch_task<'v>(&mut self, current_func: &'v Function, functions: &'v HashMap<&'static str, Function>)
+			-> Result<&'v Function, Error> {
+		self.task_switch = false;
+		self.tasks[self.current_task].context.function = current_func.name;
+		let count = self.tasks.len();
+		let next: usize = (1..=count)
+				.map(|i| (self.current_task + i) % count)
+				.find(|&i| self.is_task_ready(i))
+				.ok_or_else(|| Error::Deadlock(self.blocked_tasks()))?;
+		self.tasks[next].state = TaskState::Ready;
+		if next == self.current_task {
+			return Ok(current_func);
+		}
+		let context = self.save_context(current_func.name);
+		self.tasks[self.current_task].context = context;
+		mem::swap(&mut self.resumers, &mut self.tasks[self.current_task].resumers);
+		let context = mem::take(&mut self.tasks[next].context);
+		mem::swap(&mut self.resumers, &mut self.tasks[next].resumers);
+		self.current_task = next;
+		let name = self.load_context(context);
+		functions.get(name).ok_or(Error::FunctionIsNotDefined(name))
+	}
+
+	/// Blocks the running task, the opcode is executed again when the task is ready.
+	fn block_task(&mut self, state: TaskState) {
+		self.tasks[self.current_task].state = state;
+		self.task_switch = true;
+	}
+
+	fn get_channel(&mut self, handle: i64) -> Result<&mut Channel, Error> {
+		self.channels.get_mut(handle as usize).ok_or(Error::NoSuchChannel(handle))
+	}
+
+	fn is_task_ready(&self, index: usize) -> bool {
+		match self.tasks[index].state {
+			TaskState::Ready => true,
+			TaskState::Joining(handle) => self.tasks[handle].state == TaskState::Finished,
+			TaskState::Sending(handle) => !self.channels[handle].would_block_send(),
+			TaskState::Receiving(handle) => !self.channels[handle].would_block_recv(),
+			TaskState::Finished => false,
+		}
+	}
+
+	fn blocked_tasks(&self) -> Vec<(usize, &'static str)> {
+		self.tasks.iter().enumerate()
+				.filter(|&(_, task)| task.state != TaskState::Finished)
+				.map(|(i, task)| (i, task.context.function))
+				.collect()
+	}
+
+	/// Runs until entry function returns.
+	/// Call stack is cleared on failure, so the VM is ready for a next run.
+	fn execute(&mut self, program: &Program, entry: &'static str) -> Result<(), Error> {
+		let result = self.execute_loop(program, entry);
+		if result.is_err() {
+			self.clear_call_stack();
+		}
+		result
+	}
+
+	/// Main loop of the machine.
+	fn execute_loop(&mut self, program: &Program, entry: &'static str) -> Result<(), Error> {
+		let functions: &HashMap<&'static str, Function> = program.functions();
+		let opcodes: &[Opcode] = program.opcodes();
+
+		let mut current_function: &Function = functions
+				.get(entry)
+				.ok_or(Error::FunctionIsNotDefined(entry))?;
+
+		{
+			// Init stack (empty right now), first opcode to start with and return address.
+			// TODO: is it OK to write last entry's opcode address as a return address?
+			self.stack_pointer = 0;
+			self.opcode_pointer = current_function.opcodes_range.start;
+			self.return_stack.push((entry, current_function.opcodes_range.end, self.stack_pointer));
+			// Context of the running task lives in the VM, so the entry task is a placeholder.
+			self.tasks.push(Task {
+				state: TaskState::Ready,
+				entry,
+				context: Context::default(),
+				resumers: Vec::new(),
+			});
+		}
+
+		let mut turns: u64 = 0;
+		loop {
+			if self.return_stack.is_empty() {
+				if let Some(resumer) = self.resumers.pop() {
+					// Coroutine returned.
+					self.coroutines[resumer.coroutine].state = CoroutineState::Finished;
+					current_function = self.return_to_resumer(resumer, None, functions)?;
+					continue;
+				}
+				if self.current_task == 0 {
+					break;
+				}
+				self.tasks[self.current_task].state = TaskState::Finished;
+				self.task_switch = true;
+			}
+			if self.task_switch {
+				current_function = self.switch_task(current_function, functions)?;
+			}
+			if let Some(limit) = self.fuel_limit {
+				if turns >= limit {
+					return Err(Error::FuelExhausted(limit));
+				}
+				turns += 1;
+			}
+			// Redundant check that should never fail.
+			if current_function.is_opcode_in_range(self.opcode_pointer) {
+				let pointer = self.opcode_pointer;
+				current_function = match self.turn(&opcodes[pointer], current_function, program) {
+					Ok(function) => function,
+					Err(error) => self.raise(error, pointer, current_function, functions)?,
+				};
+			} else {
+				panic!("All safety measures failed. Running opcode is out of current function. Aborting...");
+			}
+		}
+		Ok(())
+	}
+
+	/// Static checks of a program against this VM, see `Program::verify`.
+	pub fn verify(&self, program: &Program) -> Result<(), Error> {
+		program.verify(&self.config)
+	}
+
+	/// Limit number of executed opcodes per run. None means no limit.
+	pub fn set_fuel_limit(&mut self, limit: Option<u64>) {
+		self.fuel_limit = limit;
+	}
+
+	/// Get value from integer_register or returns an error
+	fn get_int_register(&self, reg_number: usize) -> Result<i64, Error> {
 		let number_of_registers = self.integer_register.len();
 		if reg_number < number_of_registers {
 			Ok(self.integer_register[reg_number])
@@ -278,10 +1423,10 @@ impl ParallaxVm {
 		}
 	}
 
-	#[cfg(float)]
+	#[cfg(feature = "float")]
 	/// Get value from floating_register or returns an error
 	fn get_float_register(&self, reg_number: usize) -> Result<f64, Error> {
-		let number_of_registers = self.integer_register.len();
+		let number_of_registers = self.floating_register.len();
 		if reg_number < number_of_registers {
 			Ok(self.floating_register[reg_number])
 		} else {
@@ -289,7 +1434,7 @@ impl ParallaxVm {
 		}
 	}
 
-	#[cfg(float)]
+	#[cfg(feature = "float")]
 	/// Set value from floating_register or returns an error
 	fn set_float_register(&mut self, reg_number: usize, new_value: f64) -> Result<(), Error> {
 		let number_of_registers = self.floating_register.len();
@@ -301,11 +1446,36 @@ impl ParallaxVm {
 		}
 	}
 
-	#[cfg(test)]
-	/// Get a read-only access to VM's registers for test purposes
+	/// Get a read-only access to VM's integer registers
 	pub fn get_integer_registers(&self) -> &[i64] {
 		&self.integer_register
 	}
+
+	#[cfg(feature = "float")]
+	/// Get a read-only access to VM's floating registers
+	pub fn get_floating_registers(&self) -> &[f64] {
+		&self.floating_register
+	}
+
+	/// Flags set by the last integer Compare or arithmetic opcode
+	pub fn get_flags(&self) -> Flags {
+		self.flags
+	}
+
+	/// Get a read-only access to VM's memory
+	pub fn get_memory(&self) -> &[u8] {
+		self.memory.bytes()
+	}
+
+	/// Get access to VM's memory, e.g. to put input data before a run
+	pub fn get_memory_mut(&mut self) -> &mut [u8] {
+		self.memory.bytes_mut()
+	}
+
+	/// Configuration this VM was created with.
+	pub fn config(&self) -> VmConfig {
+		self.config
+	}
 }
 
 
@@ -313,6 +1483,8 @@ impl ParallaxVm {
 #[cfg(test)]
 mod tests {
 	use super::*;
+	use flags::Condition;
+	use io::MemoryIo;
 
 	/// Helper function, that wraps piece of code into main function.
 	fn wrap_into_main(piece_of_code: &mut Vec<Opcode>) -> Vec<Opcode> {
@@ -474,44 +1646,1267 @@ mod tests {
 	}
 
 	#[test]
-	fn check_no_label_jump_fail() {
-		let label_name = "v1";
+	fn check_mul_div() {
 		let mut vm = ParallaxVm::default();
 		let application = wrap_into_main(&mut vec![
-			Opcode::JumpNotZero(label_name, OperandType::IntegerRegister(29)),
+			Opcode::Move(OperandType::IntegerRegister(0), OperandType::IntegerConstant(-7)),
+			Opcode::Mul(OperandType::IntegerRegister(1), OperandType::IntegerRegister(0), OperandType::IntegerConstant(6)),
+			Opcode::Div(OperandType::IntegerRegister(2), OperandType::IntegerRegister(1), OperandType::IntegerConstant(4)),
+			Opcode::Abs(OperandType::IntegerRegister(3), OperandType::IntegerRegister(2)),
+			Opcode::Neg(OperandType::IntegerRegister(4), OperandType::IntegerRegister(3)),
+			Opcode::Min(OperandType::IntegerRegister(5), OperandType::IntegerRegister(0), OperandType::IntegerRegister(4)),
+			Opcode::Max(OperandType::IntegerRegister(6), OperandType::IntegerRegister(0), OperandType::IntegerRegister(4)),
+			Opcode::Mul(OperandType::IntegerRegister(7), OperandType::IntegerConstant(i64::MAX), OperandType::IntegerConstant(2)),
+		]);
+		let run_result = vm.run(application.as_slice());
+		assert!(run_result.is_ok());
+		assert_eq!(&vm.get_integer_registers()[..8], &[-7, -42, -10, 10, -10, -10, -7, -2]);
+	}
+
+	#[test]
+	fn check_div_fail1() {
+		let mut vm = ParallaxVm::default();
+		let application = wrap_into_main(&mut vec![
+			Opcode::Div(OperandType::IntegerRegister(0), OperandType::IntegerConstant(5), OperandType::IntegerRegister(1)),
 		]);
 
 		let run_result = vm.run(application.as_slice());
-		assert_eq!(run_result, Err(Error::LabelDoesNotExist(label_name)));
+		assert_eq!(run_result, Err(Error::DivisionByZero));
 		assert_eq!(vm.get_integer_registers(), &[0; 32]);
 	}
 
 	#[test]
-	fn generic_test1() {
+	fn check_multiword_add() {
 		let mut vm = ParallaxVm::default();
-		let application: Vec<Opcode> = vec![
-			Opcode::FunctionStart("main"),
-			Opcode::Move(OperandType::IntegerRegister(1), OperandType::IntegerConstant(0x25)),
-			Opcode::Add(OperandType::IntegerRegister(2), OperandType::IntegerConstant(3), OperandType::IntegerConstant(5)),
-			Opcode::Jump("skip_next_opcode"),
-			Opcode::Add(OperandType::IntegerRegister(3), OperandType::IntegerRegister(2), OperandType::IntegerConstant(-1)),
-			Opcode::Label("skip_next_opcode"),
-			Opcode::Move(OperandType::IntegerRegister(10), OperandType::IntegerConstant(17)),
-			Opcode::Call("test1"),
-			Opcode::Sub(OperandType::IntegerRegister(2), OperandType::IntegerRegister(2), OperandType::IntegerConstant(6)),
-			Opcode::Return,
-			Opcode::FunctionEnd,
-			Opcode::FunctionStart("test1"),
-			Opcode::Add(OperandType::IntegerRegister(10), OperandType::IntegerConstant(10), OperandType::IntegerConstant(5)),
-			Opcode::JumpEqual("exit", OperandType::IntegerRegister(10), OperandType::IntegerConstant(15)),
-			Opcode::Move(OperandType::IntegerRegister(9), OperandType::IntegerConstant(0xFA)),
-			Opcode::Label("exit"),
-			Opcode::Return,
-			Opcode::FunctionEnd,
-		];
+		// 128-bit numbers: (r1:r0) + (r3:r2) -> (r5:r4), then (r5:r4) - (r3:r2) -> (r7:r6).
+		let application = wrap_into_main(&mut vec![
+			Opcode::Move(OperandType::IntegerRegister(0), OperandType::IntegerConstant(-1)),
+			Opcode::Move(OperandType::IntegerRegister(1), OperandType::IntegerConstant(5)),
+			Opcode::Move(OperandType::IntegerRegister(2), OperandType::IntegerConstant(2)),
+			Opcode::Move(OperandType::IntegerRegister(3), OperandType::IntegerConstant(7)),
+			Opcode::Add(OperandType::IntegerRegister(4), OperandType::IntegerRegister(0), OperandType::IntegerRegister(2)),
+			Opcode::AddCarry(OperandType::IntegerRegister(5), OperandType::IntegerRegister(1), OperandType::IntegerRegister(3)),
+			Opcode::Sub(OperandType::IntegerRegister(6), OperandType::IntegerRegister(4), OperandType::IntegerRegister(2)),
+			Opcode::SubBorrow(OperandType::IntegerRegister(7), OperandType::IntegerRegister(5), OperandType::IntegerRegister(3)),
+		]);
 		let run_result = vm.run(application.as_slice());
 		assert!(run_result.is_ok());
-		assert_eq!(vm.get_integer_registers(),
-			&[0, 37, 2, 0, 0, 0, 0, 0, 0, 0, 15, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0]);
+		assert_eq!(&vm.get_integer_registers()[4..8], &[1, 13, -1, 5]);
+		assert_eq!(vm.get_flags(), Flags{zero: false, sign: false, carry: false, overflow: false});
+	}
+
+	#[test]
+	fn check_compare_jump_if() {
+		let mut vm = ParallaxVm::default();
+		let application = wrap_into_main(&mut vec![
+			Opcode::Compare(OperandType::IntegerConstant(-1), OperandType::IntegerConstant(1)),
+			Opcode::JumpIf(Condition::Below, "signed"),
+			Opcode::Move(OperandType::IntegerRegister(0), OperandType::IntegerConstant(100)),
+			Opcode::Label("signed"),
+			Opcode::Compare(OperandType::IntegerConstant(-1), OperandType::IntegerConstant(1)),
+			Opcode::JumpIf(Condition::AboveUnsigned, "unsigned"),
+			Opcode::Move(OperandType::IntegerRegister(1), OperandType::IntegerConstant(100)),
+			Opcode::Label("unsigned"),
+			Opcode::Add(OperandType::IntegerRegister(2), OperandType::IntegerConstant(i64::MAX), OperandType::IntegerConstant(1)),
+			Opcode::JumpIf(Condition::NoOverflow, "no_overflow"),
+			Opcode::Move(OperandType::IntegerRegister(3), OperandType::IntegerConstant(1)),
+			Opcode::Label("no_overflow"),
+		]);
+		let run_result = vm.run(application.as_slice());
+		assert!(run_result.is_ok());
+		assert_eq!(&vm.get_integer_registers()[..4], &[0, 0, i64::MIN, 1]);
+	}
+
+	#[test]
+	fn check_select_move_if() {
+		let mut vm = ParallaxVm::default();
+		let application = wrap_into_main(&mut vec![
+			Opcode::Move(OperandType::IntegerRegister(0), OperandType::IntegerConstant(3)),
+			Opcode::Select(OperandType::IntegerRegister(1), OperandType::IntegerRegister(0),
+				OperandType::IntegerConstant(10), OperandType::IntegerConstant(20)),
+			Opcode::Select(OperandType::IntegerRegister(2), OperandType::IntegerRegister(31),
+				OperandType::IntegerConstant(10), OperandType::IntegerConstant(20)),
+			Opcode::Move(OperandType::IntegerRegister(3), OperandType::IntegerConstant(-1)),
+			Opcode::MoveIfBelow(OperandType::IntegerRegister(3), OperandType::IntegerRegister(0),
+				OperandType::IntegerRegister(0), OperandType::IntegerConstant(5)),
+			Opcode::Move(OperandType::IntegerRegister(4), OperandType::IntegerConstant(-1)),
+			Opcode::MoveIfAbove(OperandType::IntegerRegister(4), OperandType::IntegerRegister(0),
+				OperandType::IntegerRegister(0), OperandType::IntegerConstant(5)),
+			Opcode::MoveIfEqual(OperandType::IntegerRegister(5), OperandType::IntegerConstant(7),
+				OperandType::IntegerRegister(0), OperandType::IntegerConstant(3)),
+		]);
+		let run_result = vm.run(application.as_slice());
+		assert!(run_result.is_ok());
+		assert_eq!(&vm.get_integer_registers()[..6], &[3, 10, 20, 3, -1, 7]);
+	}
+
+	#[test]
+	fn check_unsigned_jumps() {
+		let jump_taken = |jump: Opcode| -> bool {
+			let mut vm = ParallaxVm::default();
+			let application = wrap_into_main(&mut vec![
+				jump,
+				Opcode::Move(OperandType::IntegerRegister(0), OperandType::IntegerConstant(1)),
+				Opcode::Label("taken"),
+			]);
+			vm.run(application.as_slice()).unwrap();
+			vm.get_integer_registers()[0] == 0
+		};
+		let min = OperandType::IntegerConstant(i64::MIN);
+		let max = OperandType::IntegerConstant(i64::MAX);
+		let minus_one = OperandType::IntegerConstant(-1);
+		let zero = OperandType::IntegerConstant(0);
+		// i64::MAX = 0x7FFF..., i64::MIN = 0x8000..., -1 = 0xFFFF... as u64.
+		assert!(jump_taken(Opcode::JumpBelowUnsigned("taken", max, min)));
+		assert!(!jump_taken(Opcode::JumpBelow("taken", max, min)));
+		assert!(jump_taken(Opcode::JumpAboveUnsigned("taken", minus_one, max)));
+		assert!(!jump_taken(Opcode::JumpAboveUnsigned("taken", zero, minus_one)));
+		assert!(jump_taken(Opcode::JumpBelowEqualUnsigned("taken", min, min)));
+		assert!(!jump_taken(Opcode::JumpBelowEqualUnsigned("taken", min, max)));
+		assert!(jump_taken(Opcode::JumpAboveEqualUnsigned("taken", min, max)));
+		assert!(jump_taken(Opcode::JumpAboveEqualUnsigned("taken", zero, zero)));
+		assert!(!jump_taken(Opcode::JumpAboveEqualUnsigned("taken", zero, min)));
+	}
+
+	#[test]
+	fn check_load_store() {
+		let mut vm = ParallaxVm::default();
+		vm.get_memory_mut()[100..104].copy_from_slice(&[0xCA, 0xFE, 0x80, 0x01]);
+		let format = |name| MemoryFormat::from_name(name).unwrap();
+		let application = wrap_into_main(&mut vec![
+			Opcode::Move(OperandType::IntegerRegister(0), OperandType::IntegerConstant(100)),
+			Opcode::Load(OperandType::IntegerRegister(1), OperandType::IntegerRegister(0), format("u16be")),
+			Opcode::Load(OperandType::IntegerRegister(2), OperandType::IntegerRegister(0), format("i16le")),
+			Opcode::Load(OperandType::IntegerRegister(3), OperandType::IntegerConstant(102), format("i8")),
+			Opcode::Load(OperandType::IntegerRegister(4), OperandType::IntegerConstant(100), format("u32le")),
+			Opcode::Store(OperandType::IntegerConstant(0), OperandType::IntegerConstant(0x1234_5678), format("u32be")),
+			Opcode::Move(OperandType::Memory(8), OperandType::IntegerConstant(-2)),
+			Opcode::Add(OperandType::IntegerRegister(5), OperandType::Memory(8), OperandType::IntegerConstant(1)),
+		]);
+		let run_result = vm.run(application.as_slice());
+		assert!(run_result.is_ok());
+		assert_eq!(&vm.get_integer_registers()[1..6], &[0xCAFE, -0x0136, -128, 0x0180_FECA, -1]);
+		assert_eq!(&vm.get_memory()[..12], &[0x12, 0x34, 0x56, 0x78, 0, 0, 0, 0, 0xFE, 0xFF, 0xFF, 0xFF]);
+	}
+
+	#[test]
+	fn check_load_fail() {
+		let mut vm = ParallaxVm::new(VmConfig{memory_size: 16, ..VmConfig::default()}, Box::new(StdIo));
+		let application = wrap_into_main(&mut vec![
+			Opcode::Load(OperandType::IntegerRegister(0), OperandType::IntegerConstant(14),
+				MemoryFormat::from_name("u32le").unwrap()),
+		]);
+		assert_eq!(vm.run(application.as_slice()), Err(Error::MemoryOutOfBounds(14, 4)));
+
+		let application = wrap_into_main(&mut vec![
+			Opcode::Move(OperandType::Memory(9), OperandType::IntegerConstant(1)),
+		]);
+		let program = Program::new(&application).unwrap();
+		assert_eq!(vm.verify(&program), Err(Error::MemoryOutOfBounds(9, 8)));
+
+		// Load and Store are checked with the width of their format.
+		let application = wrap_into_main(&mut vec![
+			Opcode::Load(OperandType::IntegerRegister(0), OperandType::Memory(15), MemoryFormat::from_name("u8").unwrap()),
+			Opcode::Store(OperandType::Memory(14), OperandType::IntegerConstant(1), MemoryFormat::from_name("u16le").unwrap()),
+		]);
+		let program = Program::new(&application).unwrap();
+		assert_eq!(vm.verify(&program), Ok(()));
+		assert!(vm.run_program(&program, "main").is_ok());
+
+		let application = wrap_into_main(&mut vec![
+			Opcode::Store(OperandType::Memory(14), OperandType::IntegerConstant(1), MemoryFormat::from_name("u32le").unwrap()),
+		]);
+		let program = Program::new(&application).unwrap();
+		assert_eq!(vm.verify(&program), Err(Error::MemoryOutOfBounds(14, 4)));
+	}
+
+	#[test]
+	fn check_addressing_modes() {
+		let mut vm = ParallaxVm::default();
+		// Fill an array of 4 words at address 64 and sum it back.
+		let application = wrap_into_main(&mut vec![
+			Opcode::Move(OperandType::IntegerRegister(0), OperandType::IntegerConstant(64)),
+			Opcode::Label("fill"),
+			Opcode::Add(OperandType::IntegerRegister(2), OperandType::IntegerRegister(1), OperandType::IntegerConstant(10)),
+			Opcode::Move(OperandType::MemoryIndex(0, 1, 8, 0), OperandType::IntegerRegister(2)),
+			Opcode::Add(OperandType::IntegerRegister(1), OperandType::IntegerRegister(1), OperandType::IntegerConstant(1)),
+			Opcode::JumpBelow("fill", OperandType::IntegerRegister(1), OperandType::IntegerConstant(4)),
+			Opcode::Move(OperandType::IntegerRegister(3), OperandType::MemoryBase(0, 0)),
+			Opcode::Add(OperandType::IntegerRegister(3), OperandType::IntegerRegister(3), OperandType::MemoryBase(0, 8)),
+			Opcode::Add(OperandType::IntegerRegister(3), OperandType::IntegerRegister(3), OperandType::MemoryIndex(0, 1, 4, 0)),
+			Opcode::Add(OperandType::IntegerRegister(3), OperandType::IntegerRegister(3), OperandType::Memory(88)),
+			Opcode::Load(OperandType::IntegerRegister(4), OperandType::MemoryBase(0, 9), MemoryFormat::from_name("u8").unwrap()),
+		]);
+		let run_result = vm.run(application.as_slice());
+		assert!(run_result.is_ok());
+		assert_eq!(&vm.get_integer_registers()[..5], &[64, 4, 13, 46, 0]);
+
+		let application = wrap_into_main(&mut vec![
+			Opcode::Move(OperandType::IntegerRegister(0), OperandType::MemoryBase(5, -8)),
+		]);
+		assert_eq!(vm.run(application.as_slice()), Err(Error::MemoryOutOfBounds(-8i64 as usize, 8)));
+	}
+
+	#[test]
+	fn check_data() {
+		let mut vm = ParallaxVm::default();
+		let mut application = vec![
+			Opcode::ReadOnlyData("squares", MemoryFormat::from_name("u8").unwrap(), &[0, 1, 4, 9, 16]),
+			Opcode::Data("counter", MemoryFormat::WORD, &[100]),
+		];
+		application.append(&mut wrap_into_main(&mut vec![
+			Opcode::Move(OperandType::IntegerRegister(0), OperandType::Symbol("squares")),
+			Opcode::Move(OperandType::IntegerRegister(1), OperandType::Symbol("counter")),
+			Opcode::Load(OperandType::IntegerRegister(2), OperandType::MemoryBase(0, 3), MemoryFormat::from_name("u8").unwrap()),
+			Opcode::Add(OperandType::MemorySymbol("counter", 0), OperandType::MemorySymbol("counter", 0), OperandType::IntegerRegister(2)),
+			Opcode::Move(OperandType::IntegerRegister(3), OperandType::MemoryBase(1, 0)),
+		]));
+		let program = Program::new(&application).unwrap();
+		assert_eq!(program.data_size(), 16);
+		assert_eq!(vm.run_program(&program, "main"), Ok(0));
+		assert_eq!(&vm.get_integer_registers()[..4], &[0, 8, 9, 109]);
+		// Data is restored before every run.
+		assert_eq!(vm.run_program(&program, "main"), Ok(0));
+		assert_eq!(vm.get_integer_registers()[3], 109);
+
+		let mut application = vec![
+			Opcode::ReadOnlyData("squares", MemoryFormat::from_name("u8").unwrap(), &[0, 1, 4, 9, 16]),
+		];
+		application.append(&mut wrap_into_main(&mut vec![
+			Opcode::Store(OperandType::Symbol("squares"), OperandType::IntegerConstant(1), MemoryFormat::from_name("u8").unwrap()),
+		]));
+		assert_eq!(vm.run(&application), Err(Error::ReadOnlyMemory(0)));
+	}
+
+	#[test]
+	fn check_data_fail() {
+		let application = wrap_into_main(&mut vec![
+			Opcode::Move(OperandType::IntegerRegister(0), OperandType::Symbol("nothing")),
+		]);
+		assert_eq!(Program::new(&application).unwrap_err(), Error::SymbolIsNotDefined("nothing"));
+
+		let application = vec![
+			Opcode::Data("twice", MemoryFormat::WORD, &[1]),
+			Opcode::Data("twice", MemoryFormat::WORD, &[2]),
+		];
+		assert_eq!(Program::new(&application).unwrap_err(), Error::SymbolIsAlreadyDefined("twice"));
+
+		let application = wrap_into_main(&mut vec![
+			Opcode::Data("inside", MemoryFormat::WORD, &[1]),
+		]);
+		assert_eq!(Program::new(&application).unwrap_err(), Error::BrokenFunctionDefinition("main"));
+	}
+
+	#[test]
+	fn check_heap() {
+		let mut vm = ParallaxVm::default();
+		let application = vec![
+			Opcode::Data("value", MemoryFormat::WORD, &[5]),
+			Opcode::FunctionStart("main"),
+			Opcode::Alloc(OperandType::IntegerRegister(0), OperandType::IntegerConstant(12)),
+			Opcode::Alloc(OperandType::IntegerRegister(1), OperandType::IntegerConstant(8)),
+			Opcode::Move(OperandType::MemoryBase(0, 0), OperandType::IntegerConstant(42)),
+			Opcode::Realloc(OperandType::IntegerRegister(2), OperandType::IntegerRegister(0), OperandType::IntegerConstant(64)),
+			Opcode::Move(OperandType::IntegerRegister(3), OperandType::MemoryBase(2, 0)),
+			Opcode::Free(OperandType::IntegerRegister(1)),
+			Opcode::Free(OperandType::IntegerRegister(2)),
+			Opcode::Alloc(OperandType::IntegerRegister(4), OperandType::IntegerConstant(0)),
+			Opcode::Return,
+			Opcode::FunctionEnd,
+		];
+		vm.run(&application).unwrap();
+		// Heap starts after data, the first block can't grow in place.
+		assert_eq!(&vm.get_integer_registers()[..5], &[8, 24, 32, 42, 8]);
+
+		let mut vm = ParallaxVm::new(VmConfig{memory_size: 64, ..VmConfig::default()}, Box::new(StdIo));
+		let application = wrap_into_main(&mut vec![
+			Opcode::Alloc(OperandType::IntegerRegister(0), OperandType::IntegerConstant(57)),
+		]);
+		assert_eq!(vm.run(&application), Err(Error::OutOfMemory(57)));
+	}
+
+	#[test]
+	fn check_heap_fail() {
+		let mut vm = ParallaxVm::new(VmConfig{debug_heap: true, ..VmConfig::default()}, Box::new(StdIo));
+		let free_twice = wrap_into_main(&mut vec![
+			Opcode::Alloc(OperandType::IntegerRegister(0), OperandType::IntegerConstant(8)),
+			Opcode::Free(OperandType::IntegerRegister(0)),
+			Opcode::Free(OperandType::IntegerRegister(0)),
+		]);
+		assert_eq!(vm.run(&free_twice), Err(Error::DoubleFree(8)));
+
+		let use_after_free = wrap_into_main(&mut vec![
+			Opcode::Alloc(OperandType::IntegerRegister(0), OperandType::IntegerConstant(16)),
+			Opcode::Free(OperandType::IntegerRegister(0)),
+			Opcode::Store(OperandType::MemoryBase(0, 8), OperandType::IntegerConstant(1), MemoryFormat::from_name("u8").unwrap()),
+		]);
+		assert_eq!(vm.run(&use_after_free), Err(Error::UseAfterFree(16)));
+
+		let wrong_pointer = wrap_into_main(&mut vec![
+			Opcode::Free(OperandType::IntegerConstant(0)),
+		]);
+		assert_eq!(vm.run(&wrong_pointer), Err(Error::InvalidPointer(0)));
+
+		let negative_size = wrap_into_main(&mut vec![
+			Opcode::Alloc(OperandType::IntegerRegister(0), OperandType::IntegerConstant(-1)),
+		]);
+		assert_eq!(vm.run(&negative_size), Err(Error::InvalidHeapSize(-1)));
+
+		let negative_resize = wrap_into_main(&mut vec![
+			Opcode::Alloc(OperandType::IntegerRegister(0), OperandType::IntegerConstant(8)),
+			Opcode::Realloc(OperandType::IntegerRegister(0), OperandType::IntegerRegister(0), OperandType::IntegerConstant(-8)),
+		]);
+		assert_eq!(vm.run(&negative_resize), Err(Error::InvalidHeapSize(-8)));
+
+		// Like other heap failures it can be caught.
+		let caught = wrap_into_main(&mut vec![
+			Opcode::Protect("start", "end", "end"),
+			Opcode::Label("start"),
+			Opcode::Alloc(OperandType::IntegerRegister(0), OperandType::IntegerConstant(-1)),
+			Opcode::Label("end"),
+			Opcode::Catch(OperandType::IntegerRegister(1)),
+		]);
+		assert_eq!(vm.run(&caught), Ok(0));
+		assert_eq!(vm.get_integer_registers()[1], Error::InvalidHeapSize(-1).exit_code() as i64);
+
+		// Without debug mode freed memory is still accessible.
+		let mut vm = ParallaxVm::default();
+		assert_eq!(vm.run(&use_after_free), Ok(0));
+	}
+
+	#[test]
+	fn check_tasks() {
+		let io = MemoryIo::default();
+		let mut vm = ParallaxVm::with_io(Box::new(io.clone()));
+		let application = vec![
+			Opcode::FunctionStart("main"),
+			Opcode::Spawn(OperandType::IntegerRegister(5), "worker", &[OperandType::IntegerConstant(1)]),
+			Opcode::Spawn(OperandType::IntegerRegister(6), "worker", &[OperandType::IntegerConstant(2)]),
+			Opcode::PrintString("m"),
+			Opcode::Yield,
+			Opcode::PrintString("m"),
+			Opcode::Join(OperandType::IntegerRegister(5)),
+			Opcode::Move(OperandType::IntegerRegister(7), OperandType::IntegerRegister(0)),
+			Opcode::Join(OperandType::IntegerRegister(6)),
+			Opcode::Return,
+			Opcode::FunctionEnd,
+			Opcode::FunctionStart("worker"),
+			Opcode::Signature(1, 1),
+			Opcode::PrintInteger(OperandType::IntegerRegister(0)),
+			Opcode::Yield,
+			Opcode::PrintInteger(OperandType::IntegerRegister(0)),
+			Opcode::Mul(OperandType::IntegerRegister(0), OperandType::IntegerRegister(0), OperandType::IntegerConstant(10)),
+			Opcode::Return,
+			Opcode::FunctionEnd,
+		];
+		let program = Program::new(&application).unwrap();
+		assert_eq!(vm.verify(&program), Ok(()));
+		vm.run_program(&program, "main").unwrap();
+		assert_eq!(io.output(), "m12m12");
+		assert_eq!(vm.get_integer_registers()[0], 20);
+		assert_eq!(&vm.get_integer_registers()[5..8], &[1, 2, 10]);
+	}
+
+	#[test]
+	fn check_tasks_fail() {
+		let mut vm = ParallaxVm::default();
+		let application = vec![
+			Opcode::FunctionStart("main"),
+			Opcode::Spawn(OperandType::IntegerRegister(1), "waiter", &[]),
+			Opcode::Join(OperandType::IntegerRegister(1)),
+			Opcode::Return,
+			Opcode::FunctionEnd,
+			Opcode::FunctionStart("waiter"),
+			Opcode::Join(OperandType::IntegerConstant(0)),
+			Opcode::Return,
+			Opcode::FunctionEnd,
+		];
+		assert_eq!(vm.run(&application), Err(Error::Deadlock(vec![(0, "main"), (1, "waiter")])));
+
+		let application = wrap_into_main(&mut vec![
+			Opcode::Join(OperandType::IntegerConstant(7)),
+		]);
+		assert_eq!(vm.run(&application), Err(Error::NoSuchTask(7)));
+
+		let application = vec![
+			Opcode::FunctionStart("main"),
+			Opcode::Spawn(OperandType::IntegerRegister(1), "waiter", &[OperandType::IntegerConstant(7)]),
+			Opcode::Return,
+			Opcode::FunctionEnd,
+			Opcode::FunctionStart("waiter"),
+			Opcode::Return,
+			Opcode::FunctionEnd,
+		];
+		let program = Program::new(&application).unwrap();
+		assert_eq!(vm.verify(&program), Err(Error::WrongNumberOfArguments("waiter", 0, 1)));
+	}
+
+	#[test]
+	fn check_channels() {
+		let mut vm = ParallaxVm::default();
+		let application = vec![
+			Opcode::FunctionStart("main"),
+			Opcode::ChanNew(OperandType::IntegerRegister(1), OperandType::IntegerConstant(1)),
+			Opcode::Spawn(OperandType::IntegerRegister(2), "producer", &[OperandType::IntegerRegister(1)]),
+			Opcode::Recv(OperandType::IntegerRegister(6), OperandType::IntegerRegister(1)),
+			Opcode::Label("loop"),
+			Opcode::TryRecv(OperandType::IntegerRegister(4), OperandType::IntegerRegister(3), OperandType::IntegerRegister(1)),
+			Opcode::JumpZero("wait", OperandType::IntegerRegister(4)),
+			Opcode::JumpBelow("done", OperandType::IntegerRegister(4), OperandType::IntegerConstant(0)),
+			Opcode::Add(OperandType::IntegerRegister(5), OperandType::IntegerRegister(5), OperandType::IntegerRegister(3)),
+			Opcode::Jump("loop"),
+			Opcode::Label("wait"),
+			Opcode::Yield,
+			Opcode::Jump("loop"),
+			Opcode::Label("done"),
+			Opcode::TrySend(OperandType::IntegerRegister(7), OperandType::IntegerRegister(1), OperandType::IntegerConstant(0)),
+			Opcode::Return,
+			Opcode::FunctionEnd,
+			Opcode::FunctionStart("producer"),
+			Opcode::Signature(1, 0),
+			Opcode::Move(OperandType::IntegerRegister(1), OperandType::IntegerConstant(1)),
+			Opcode::Label("loop"),
+			Opcode::Send(OperandType::IntegerRegister(0), OperandType::IntegerRegister(1)),
+			Opcode::Add(OperandType::IntegerRegister(1), OperandType::IntegerRegister(1), OperandType::IntegerConstant(1)),
+			Opcode::JumpBelowEqual("loop", OperandType::IntegerRegister(1), OperandType::IntegerConstant(5)),
+			Opcode::Close(OperandType::IntegerRegister(0)),
+			Opcode::Return,
+			Opcode::FunctionEnd,
+		];
+		vm.run(&application).unwrap();
+		assert_eq!(&vm.get_integer_registers()[4..8], &[-1, 14, 1, -1]);
+	}
+
+	#[test]
+	fn check_channels_fail() {
+		let mut vm = ParallaxVm::default();
+		let application = wrap_into_main(&mut vec![
+			Opcode::ChanNew(OperandType::IntegerRegister(1), OperandType::IntegerConstant(0)),
+			Opcode::Recv(OperandType::IntegerRegister(0), OperandType::IntegerRegister(1)),
+		]);
+		assert_eq!(vm.run(&application), Err(Error::Deadlock(vec![(0, "main")])));
+
+		let application = vec![
+			Opcode::FunctionStart("main"),
+			Opcode::ChanNew(OperandType::IntegerRegister(0), OperandType::IntegerConstant(1)),
+			Opcode::Spawn(OperandType::IntegerRegister(1), "producer", &[OperandType::IntegerRegister(0)]),
+			Opcode::Join(OperandType::IntegerRegister(1)),
+			Opcode::Return,
+			Opcode::FunctionEnd,
+			Opcode::FunctionStart("producer"),
+			Opcode::Signature(1, 0),
+			Opcode::Send(OperandType::IntegerRegister(0), OperandType::IntegerConstant(1)),
+			Opcode::Send(OperandType::IntegerRegister(0), OperandType::IntegerConstant(2)),
+			Opcode::Return,
+			Opcode::FunctionEnd,
+		];
+		assert_eq!(vm.run(&application), Err(Error::Deadlock(vec![(0, "main"), (1, "producer")])));
+
+		let application = wrap_into_main(&mut vec![
+			Opcode::ChanNew(OperandType::IntegerRegister(1), OperandType::IntegerConstant(0)),
+			Opcode::Close(OperandType::IntegerRegister(1)),
+			Opcode::Send(OperandType::IntegerRegister(1), OperandType::IntegerConstant(1)),
+		]);
+		assert_eq!(vm.run(&application), Err(Error::ChannelClosed(0)));
+
+		let application = wrap_into_main(&mut vec![
+			Opcode::Close(OperandType::IntegerConstant(3)),
+		]);
+		assert_eq!(vm.run(&application), Err(Error::NoSuchChannel(3)));
+	}
+
+	#[test]
+	fn check_atomics() {
+		let mut vm = ParallaxVm::default();
+		let counter = OperandType::Symbol("counter");
+		let application = vec![
+			Opcode::Data("lock", MemoryFormat::WORD, &[0]),
+			Opcode::Data("counter", MemoryFormat::WORD, &[0]),
+			Opcode::FunctionStart("main"),
+			Opcode::Spawn(OperandType::IntegerRegister(1), "worker", &[]),
+			Opcode::Spawn(OperandType::IntegerRegister(2), "worker", &[]),
+			Opcode::Join(OperandType::IntegerRegister(1)),
+			Opcode::Join(OperandType::IntegerRegister(2)),
+			Opcode::FetchXor(OperandType::IntegerRegister(3), counter, OperandType::IntegerConstant(3)),
+			Opcode::FetchAnd(OperandType::IntegerRegister(4), counter, OperandType::IntegerConstant(4)),
+			Opcode::FetchOr(OperandType::IntegerRegister(5), counter, OperandType::IntegerConstant(1)),
+			Opcode::FetchAdd(OperandType::IntegerRegister(6), counter, OperandType::IntegerConstant(-10)),
+			Opcode::AtomicLoad(OperandType::IntegerRegister(7), counter),
+			Opcode::Return,
+			Opcode::FunctionEnd,
+			// Increment counter three times, value is read and written back with a yield in between.
+			Opcode::FunctionStart("worker"),
+			Opcode::Move(OperandType::IntegerRegister(1), OperandType::IntegerConstant(3)),
+			Opcode::Label("retry"),
+			Opcode::CompareExchange(OperandType::IntegerRegister(2), OperandType::Symbol("lock"),
+				OperandType::IntegerConstant(0), OperandType::IntegerConstant(1)),
+			Opcode::JumpIf(Condition::NotEqual, "busy"),
+			Opcode::Move(OperandType::IntegerRegister(3), OperandType::MemorySymbol("counter", 0)),
+			Opcode::Yield,
+			Opcode::Add(OperandType::IntegerRegister(3), OperandType::IntegerRegister(3), OperandType::IntegerConstant(1)),
+			Opcode::Move(OperandType::MemorySymbol("counter", 0), OperandType::IntegerRegister(3)),
+			Opcode::AtomicStore(OperandType::Symbol("lock"), OperandType::IntegerConstant(0)),
+			Opcode::Fence,
+			Opcode::Sub(OperandType::IntegerRegister(1), OperandType::IntegerRegister(1), OperandType::IntegerConstant(1)),
+			Opcode::JumpNotZero("retry", OperandType::IntegerRegister(1)),
+			Opcode::Return,
+			Opcode::Label("busy"),
+			Opcode::Yield,
+			Opcode::Jump("retry"),
+			Opcode::FunctionEnd,
+		];
+		vm.run(&application).unwrap();
+		assert_eq!(&vm.get_integer_registers()[3..8], &[6, 5, 4, 5, -5]);
+
+		let application = wrap_into_main(&mut vec![
+			Opcode::AtomicLoad(OperandType::IntegerRegister(0), OperandType::IntegerConstant(4)),
+		]);
+		assert_eq!(vm.run(&application), Err(Error::UnalignedAtomic(4)));
+	}
+
+	#[test]
+	fn check_coroutines() {
+		let mut vm = ParallaxVm::default();
+		let application = vec![
+			Opcode::FunctionStart("main"),
+			Opcode::CoroutineNew(OperandType::IntegerRegister(1), "counter", &[OperandType::IntegerConstant(3)]),
+			Opcode::Label("loop"),
+			Opcode::Resume(OperandType::IntegerRegister(2), OperandType::IntegerRegister(3), OperandType::IntegerRegister(1)),
+			Opcode::JumpZero("done", OperandType::IntegerRegister(2)),
+			Opcode::Add(OperandType::IntegerRegister(4), OperandType::IntegerRegister(4), OperandType::IntegerRegister(3)),
+			Opcode::Add(OperandType::IntegerRegister(5), OperandType::IntegerRegister(5), OperandType::IntegerConstant(1)),
+			Opcode::Jump("loop"),
+			Opcode::Label("done"),
+			Opcode::Return,
+			Opcode::FunctionEnd,
+			// Yields 0, 1, ... n - 1 from a nested call, registers are not shared with main.
+			Opcode::FunctionStart("counter"),
+			Opcode::Signature(1, 0),
+			Opcode::Move(OperandType::IntegerRegister(1), OperandType::IntegerConstant(0)),
+			Opcode::Label("loop"),
+			Opcode::Call("emit"),
+			Opcode::Add(OperandType::IntegerRegister(1), OperandType::IntegerRegister(1), OperandType::IntegerConstant(1)),
+			Opcode::JumpBelow("loop", OperandType::IntegerRegister(1), OperandType::IntegerRegister(0)),
+			Opcode::Return,
+			Opcode::FunctionEnd,
+			Opcode::FunctionStart("emit"),
+			Opcode::YieldValue(OperandType::IntegerRegister(1)),
+			Opcode::Return,
+			Opcode::FunctionEnd,
+		];
+		vm.run(&application).unwrap();
+		assert_eq!(&vm.get_integer_registers()[..6], &[0, 0, 0, 2, 3, 3]);
+	}
+
+	#[test]
+	fn check_coroutines_fail() {
+		let mut vm = ParallaxVm::default();
+		let application = wrap_into_main(&mut vec![
+			Opcode::YieldValue(OperandType::IntegerConstant(1)),
+		]);
+		assert_eq!(vm.run(&application), Err(Error::YieldOutsideCoroutine));
+
+		let application = wrap_into_main(&mut vec![
+			Opcode::Resume(OperandType::IntegerRegister(0), OperandType::IntegerRegister(0), OperandType::IntegerConstant(5)),
+		]);
+		assert_eq!(vm.run(&application), Err(Error::NoSuchCoroutine(5)));
+
+		let application = vec![
+			Opcode::FunctionStart("main"),
+			Opcode::CoroutineNew(OperandType::IntegerRegister(1), "empty", &[]),
+			Opcode::Resume(OperandType::IntegerRegister(2), OperandType::IntegerRegister(3), OperandType::IntegerRegister(1)),
+			Opcode::Resume(OperandType::IntegerRegister(2), OperandType::IntegerRegister(3), OperandType::IntegerRegister(1)),
+			Opcode::Return,
+			Opcode::FunctionEnd,
+			Opcode::FunctionStart("empty"),
+			Opcode::Return,
+			Opcode::FunctionEnd,
+		];
+		assert_eq!(vm.run(&application), Err(Error::CoroutineIsNotSuspended(0)));
+	}
+
+	#[test]
+	fn check_exceptions() {
+		let mut vm = ParallaxVm::default();
+		let application = vec![
+			Opcode::FunctionStart("main"),
+			Opcode::Protect("outer_start", "outer_end", "outer"),
+			Opcode::Protect("inner_start", "inner_end", "inner"),
+			Opcode::Protect("coroutine_start", "coroutine_end", "coroutine"),
+			Opcode::Label("outer_start"),
+			Opcode::Label("inner_start"),
+			Opcode::Call("divide_by_zero"),
+			Opcode::Move(OperandType::IntegerRegister(1), OperandType::IntegerConstant(1)),
+			Opcode::Label("inner_end"),
+			Opcode::Throw(OperandType::IntegerConstant(7)),
+			Opcode::Label("outer_end"),
+			Opcode::Return,
+			Opcode::Label("inner"),
+			Opcode::Catch(OperandType::IntegerRegister(2)),
+			Opcode::Jump("inner_end"),
+			Opcode::Label("outer"),
+			Opcode::Catch(OperandType::IntegerRegister(3)),
+			Opcode::Label("coroutine_start"),
+			Opcode::CoroutineNew(OperandType::IntegerRegister(4), "throw", &[]),
+			Opcode::Resume(OperandType::IntegerRegister(5), OperandType::IntegerRegister(6), OperandType::IntegerRegister(4)),
+			Opcode::Label("coroutine_end"),
+			Opcode::Return,
+			Opcode::Label("coroutine"),
+			Opcode::Catch(OperandType::IntegerRegister(7)),
+			Opcode::Return,
+			Opcode::FunctionEnd,
+			Opcode::FunctionStart("divide_by_zero"),
+			Opcode::Div(OperandType::IntegerRegister(0), OperandType::IntegerConstant(1), OperandType::IntegerConstant(0)),
+			Opcode::Return,
+			Opcode::FunctionEnd,
+			Opcode::FunctionStart("throw"),
+			Opcode::Throw(OperandType::IntegerConstant(9)),
+			Opcode::Return,
+			Opcode::FunctionEnd,
+		];
+		let program = Program::new(&application).unwrap();
+		assert_eq!(vm.verify(&program), Ok(()));
+		vm.run_program(&program, "main").unwrap();
+		// Division by zero is caught with its exit code.
+		assert_eq!(&vm.get_integer_registers()[1..4], &[0, 31, 7]);
+		assert_eq!(vm.get_integer_registers()[7], 9);
+
+		// Exception of another task doesn't change the code a handler catches after Yield.
+		let application = vec![
+			Opcode::FunctionStart("main"),
+			Opcode::Protect("start", "end", "handler"),
+			Opcode::Spawn(OperandType::IntegerRegister(5), "worker", &[]),
+			Opcode::Label("start"),
+			Opcode::Throw(OperandType::IntegerConstant(1)),
+			Opcode::Label("end"),
+			Opcode::Return,
+			Opcode::Label("handler"),
+			Opcode::Yield,
+			Opcode::Catch(OperandType::IntegerRegister(1)),
+			Opcode::Join(OperandType::IntegerRegister(5)),
+			Opcode::Return,
+			Opcode::FunctionEnd,
+			Opcode::FunctionStart("worker"),
+			Opcode::Signature(0, 1),
+			Opcode::Protect("start", "end", "handler"),
+			Opcode::Label("start"),
+			Opcode::Throw(OperandType::IntegerConstant(2)),
+			Opcode::Label("end"),
+			Opcode::Return,
+			Opcode::Label("handler"),
+			Opcode::Catch(OperandType::IntegerRegister(0)),
+			Opcode::Return,
+			Opcode::FunctionEnd,
+		];
+		vm.run(&application).unwrap();
+		assert_eq!(&vm.get_integer_registers()[..2], &[2, 1]);
+
+		// Failed store of a yielded value is raised at Resume, not at YieldValue.
+		let application = vec![
+			Opcode::FunctionStart("main"),
+			Opcode::Protect("start", "end", "handler"),
+			Opcode::CoroutineNew(OperandType::IntegerRegister(1), "generator", &[]),
+			Opcode::Label("start"),
+			Opcode::Resume(OperandType::IntegerRegister(2), OperandType::Memory(usize::MAX), OperandType::IntegerRegister(1)),
+			Opcode::Label("end"),
+			Opcode::Return,
+			Opcode::Label("handler"),
+			Opcode::Catch(OperandType::IntegerRegister(3)),
+			Opcode::Return,
+			Opcode::FunctionEnd,
+			Opcode::FunctionStart("generator"),
+			Opcode::Protect("start", "end", "handler"),
+			Opcode::Label("start"),
+			Opcode::YieldValue(OperandType::IntegerConstant(5)),
+			Opcode::Label("end"),
+			Opcode::Return,
+			Opcode::Label("handler"),
+			Opcode::Catch(OperandType::IntegerRegister(4)),
+			Opcode::Return,
+			Opcode::FunctionEnd,
+		];
+		vm.run(&application).unwrap();
+		let code = Error::MemoryOutOfBounds(usize::MAX, 8).exit_code() as i64;
+		assert_eq!(&vm.get_integer_registers()[3..5], &[code, 0]);
+
+		// Uncaught, it fails the run.
+		let application = vec![
+			Opcode::FunctionStart("main"),
+			Opcode::CoroutineNew(OperandType::IntegerRegister(1), "generator", &[]),
+			Opcode::Resume(OperandType::IntegerRegister(2), OperandType::Memory(usize::MAX), OperandType::IntegerRegister(1)),
+			Opcode::Return,
+			Opcode::FunctionEnd,
+			Opcode::FunctionStart("generator"),
+			Opcode::YieldValue(OperandType::IntegerConstant(5)),
+			Opcode::Return,
+			Opcode::FunctionEnd,
+		];
+		assert_eq!(vm.run(&application), Err(Error::MemoryOutOfBounds(usize::MAX, 8)));
+	}
+
+	#[test]
+	fn check_exceptions_fail() {
+		let mut vm = ParallaxVm::default();
+		let application = wrap_into_main(&mut vec![
+			Opcode::Throw(OperandType::IntegerConstant(5)),
+		]);
+		assert_eq!(vm.run(&application), Err(Error::UncaughtException(5)));
+
+		let application = wrap_into_main(&mut vec![
+			Opcode::Protect("start", "end", "handler"),
+			Opcode::Label("start"),
+			Opcode::Label("end"),
+		]);
+		assert_eq!(Program::new(&application).unwrap_err(), Error::LabelDoesNotExist("handler"));
+
+		// VM limits can't be caught.
+		vm.set_fuel_limit(Some(10));
+		let application = wrap_into_main(&mut vec![
+			Opcode::Protect("loop", "end", "end"),
+			Opcode::Label("loop"),
+			Opcode::Jump("loop"),
+			Opcode::Label("end"),
+		]);
+		assert_eq!(vm.run(&application), Err(Error::FuelExhausted(10)));
+	}
+
+	#[test]
+	fn check_indirect_calls() {
+		let mut vm = ParallaxVm::default();
+		let application = vec![
+			Opcode::FunctionStart("main"),
+			Opcode::Protect("start", "end", "handler"),
+			Opcode::FuncRef(OperandType::IntegerRegister(1), "double"),
+			Opcode::FuncRef(OperandType::IntegerRegister(2), "square"),
+			// Dispatch table in memory.
+			Opcode::Move(OperandType::Memory(0), OperandType::IntegerRegister(1)),
+			Opcode::Move(OperandType::Memory(8), OperandType::IntegerRegister(2)),
+			Opcode::Move(OperandType::IntegerRegister(0), OperandType::IntegerConstant(3)),
+			Opcode::Move(OperandType::IntegerRegister(3), OperandType::Memory(0)),
+			Opcode::CallIndirect(OperandType::IntegerRegister(3)),
+			Opcode::Move(OperandType::IntegerRegister(3), OperandType::Memory(8)),
+			Opcode::CallIndirect(OperandType::IntegerRegister(3)),
+			Opcode::Label("start"),
+			Opcode::CallIndirect(OperandType::IntegerConstant(99)),
+			Opcode::Label("end"),
+			Opcode::Return,
+			Opcode::Label("handler"),
+			Opcode::Catch(OperandType::IntegerRegister(4)),
+			Opcode::Return,
+			Opcode::FunctionEnd,
+			Opcode::FunctionStart("double"),
+			Opcode::Add(OperandType::IntegerRegister(0), OperandType::IntegerRegister(0), OperandType::IntegerRegister(0)),
+			Opcode::Return,
+			Opcode::FunctionEnd,
+			Opcode::FunctionStart("square"),
+			Opcode::Mul(OperandType::IntegerRegister(0), OperandType::IntegerRegister(0), OperandType::IntegerRegister(0)),
+			Opcode::Return,
+			Opcode::FunctionEnd,
+		];
+		let program = Program::new(&application).unwrap();
+		vm.run_program(&program, "main").unwrap();
+		let double = program.function_handle("double").unwrap();
+		let square = program.function_handle("square").unwrap();
+		assert_eq!(&vm.get_integer_registers()[..5], &[36, double, square, square, 49]);
+		assert_eq!(program.function_by_handle(double).map(|function| function.name), Some("double"));
+
+		let application = wrap_into_main(&mut vec![
+			Opcode::CallIndirect(OperandType::IntegerConstant(-1)),
+		]);
+		assert_eq!(vm.run(&application), Err(Error::InvalidFunctionHandle(-1)));
+
+		// Register, that was never set, is not a handle of the first function.
+		let application = wrap_into_main(&mut vec![
+			Opcode::CallIndirect(OperandType::IntegerConstant(0)),
+		]);
+		assert_eq!(vm.run(&application), Err(Error::InvalidFunctionHandle(0)));
+
+		let application = wrap_into_main(&mut vec![
+			Opcode::FuncRef(OperandType::IntegerRegister(0), "missing"),
+		]);
+		let program = Program::new(&application).unwrap();
+		assert_eq!(vm.verify(&program), Err(Error::FunctionIsNotDefined("missing")));
+		assert_eq!(vm.run_program(&program, "main"), Err(Error::FunctionIsNotDefined("missing")));
+	}
+
+	#[test]
+	fn check_tail_calls() {
+		let mut vm = ParallaxVm::default();
+		let application = vec![
+			Opcode::FunctionStart("main"),
+			Opcode::Move(OperandType::IntegerRegister(0), OperandType::IntegerConstant(100000)),
+			Opcode::Call("count"),
+			Opcode::Move(OperandType::IntegerRegister(2), OperandType::IntegerConstant(1)),
+			Opcode::Return,
+			Opcode::FunctionEnd,
+			Opcode::FunctionStart("count"),
+			Opcode::JumpZero("done", OperandType::IntegerRegister(0)),
+			Opcode::Sub(OperandType::IntegerRegister(0), OperandType::IntegerRegister(0), OperandType::IntegerConstant(1)),
+			Opcode::Add(OperandType::IntegerRegister(1), OperandType::IntegerRegister(1), OperandType::IntegerConstant(1)),
+			Opcode::TailCall("count"),
+			Opcode::Return,
+			Opcode::Label("done"),
+			Opcode::Return,
+			Opcode::FunctionEnd,
+		];
+		let program = Program::new(&application).unwrap();
+		assert_eq!(vm.verify(&program), Ok(()));
+		vm.run_program(&program, "main").unwrap();
+		assert_eq!(&vm.get_integer_registers()[..3], &[0, 100000, 1]);
+		assert_eq!(vm.stack_pointer, 0);
+
+		// Frame of main is not counted, tail call into a smaller frame must not underflow.
+		let application = vec![
+			Opcode::FunctionStart("main"),
+			Opcode::TailCall("small"),
+			Opcode::Return,
+			Opcode::FunctionEnd,
+			Opcode::FunctionStart("small"),
+			Opcode::Move(OperandType::IntegerRegister(0), OperandType::IntegerConstant(7)),
+			Opcode::Return,
+			Opcode::FunctionEnd,
+		];
+		let mut program = Program::new(&application).unwrap();
+		program.functions_mut().get_mut("small").unwrap().stackframe_size = 4;
+		vm.run_program(&program, "main").unwrap();
+		assert_eq!(vm.get_integer_registers()[0], 7);
+		let mut vm = ParallaxVm::new(VmConfig{stack_size: 3, ..VmConfig::default()}, Box::new(StdIo));
+		assert_eq!(vm.run_program(&program, "main"), Err(Error::StackOverflow(1, vec!["small"])));
+	}
+
+	#[test]
+	fn check_tail_calls_fail() {
+		let mut vm = ParallaxVm::default();
+		let application = wrap_into_main(&mut vec![
+			Opcode::TailCall("main"),
+			Opcode::Move(OperandType::IntegerRegister(0), OperandType::IntegerConstant(1)),
+		]);
+		let program = Program::new(&application).unwrap();
+		assert_eq!(vm.verify(&program), Err(Error::TailCallNotInTailPosition("main")));
+		// Unverified program is checked at runtime.
+		assert_eq!(vm.run_program(&program, "main"), Err(Error::TailCallNotInTailPosition("main")));
+
+		let application = wrap_into_main(&mut vec![
+			Opcode::Protect("start", "end", "end"),
+			Opcode::Label("start"),
+			Opcode::TailCall("main"),
+			Opcode::Return,
+			Opcode::Label("end"),
+		]);
+		let program = Program::new(&application).unwrap();
+		assert_eq!(vm.verify(&program), Err(Error::TailCallNotInTailPosition("main")));
+		assert_eq!(vm.run_program(&program, "main"), Err(Error::TailCallNotInTailPosition("main")));
+
+		let application = wrap_into_main(&mut vec![
+			Opcode::TailCall("missing"),
+			Opcode::Return,
+		]);
+		let program = Program::new(&application).unwrap();
+		assert_eq!(vm.verify(&program), Err(Error::FunctionIsNotDefined("missing")));
+	}
+
+	#[test]
+	fn check_stack_overflow() {
+		let application = vec![
+			Opcode::FunctionStart("main"),
+			Opcode::Call("a"),
+			Opcode::Return,
+			Opcode::FunctionEnd,
+			Opcode::FunctionStart("a"),
+			Opcode::Call("b"),
+			Opcode::Return,
+			Opcode::FunctionEnd,
+			Opcode::FunctionStart("b"),
+			Opcode::Call("b"),
+			Opcode::Return,
+			Opcode::FunctionEnd,
+		];
+		let mut vm = ParallaxVm::new(VmConfig{max_call_depth: 4, ..VmConfig::default()}, Box::new(StdIo));
+		assert_eq!(vm.run(&application), Err(Error::StackOverflow(5, vec!["main", "a", "b", "b", "b"])));
+
+		// main frame is not counted in stack size.
+		let mut vm = ParallaxVm::new(VmConfig{stack_size: 25, ..VmConfig::default()}, Box::new(StdIo));
+		assert_eq!(vm.run(&application), Err(Error::StackOverflow(4, vec!["main", "a", "b", "b"])));
+
+		// Default limits stop unbounded recursion.
+		let mut vm = ParallaxVm::default();
+		match vm.run(&application) {
+			Err(Error::StackOverflow(depth, chain)) => assert_eq!(depth, chain.len()),
+			result => panic!("unexpected result {:?}", result),
+		}
+
+		// Tail calls don't grow the stack.
+		let application = vec![
+			Opcode::FunctionStart("main"),
+			Opcode::Move(OperandType::IntegerRegister(0), OperandType::IntegerConstant(1000)),
+			Opcode::Call("count"),
+			Opcode::Return,
+			Opcode::FunctionEnd,
+			Opcode::FunctionStart("count"),
+			Opcode::JumpZero("done", OperandType::IntegerRegister(0)),
+			Opcode::Sub(OperandType::IntegerRegister(0), OperandType::IntegerRegister(0), OperandType::IntegerConstant(1)),
+			Opcode::TailCall("count"),
+			Opcode::Return,
+			Opcode::Label("done"),
+			Opcode::Return,
+			Opcode::FunctionEnd,
+		];
+		let mut vm = ParallaxVm::new(VmConfig{max_call_depth: 2, stack_size: 10, ..VmConfig::default()}, Box::new(StdIo));
+		assert!(vm.run(&application).is_ok());
+	}
+
+	#[test]
+	fn check_switch() {
+		let mut vm = ParallaxVm::default();
+		// r1 = sum of values selected by indexes 0..5, index 3 and above use default.
+		let application = vec![
+			Opcode::FunctionStart("main"),
+			Opcode::Label("loop"),
+			Opcode::Switch(OperandType::IntegerRegister(0), &["zero", "one", "two"], "other"),
+			Opcode::Label("zero"),
+			Opcode::Add(OperandType::IntegerRegister(1), OperandType::IntegerRegister(1), OperandType::IntegerConstant(1)),
+			Opcode::Jump("next"),
+			Opcode::Label("one"),
+			Opcode::Add(OperandType::IntegerRegister(1), OperandType::IntegerRegister(1), OperandType::IntegerConstant(10)),
+			Opcode::Jump("next"),
+			Opcode::Label("two"),
+			Opcode::Add(OperandType::IntegerRegister(1), OperandType::IntegerRegister(1), OperandType::IntegerConstant(100)),
+			Opcode::Jump("next"),
+			Opcode::Label("other"),
+			Opcode::Add(OperandType::IntegerRegister(1), OperandType::IntegerRegister(1), OperandType::IntegerConstant(1000)),
+			Opcode::Label("next"),
+			Opcode::Add(OperandType::IntegerRegister(0), OperandType::IntegerRegister(0), OperandType::IntegerConstant(1)),
+			Opcode::JumpBelow("loop", OperandType::IntegerRegister(0), OperandType::IntegerConstant(5)),
+			Opcode::Move(OperandType::IntegerRegister(2), OperandType::IntegerConstant(-1)),
+			Opcode::Switch(OperandType::IntegerRegister(2), &["zero"], "end"),
+			Opcode::Label("end"),
+			Opcode::Return,
+			Opcode::FunctionEnd,
+		];
+		vm.run(&application).unwrap();
+		assert_eq!(&vm.get_integer_registers()[..3], &[5, 2111, -1]);
+	}
+
+	#[test]
+	fn check_switch_fail() {
+		let vm = ParallaxVm::default();
+		let application = vec![
+			Opcode::FunctionStart("main"),
+			Opcode::Label("here"),
+			Opcode::Switch(OperandType::IntegerRegister(0), &["here", "other"], "here"),
+			Opcode::Return,
+			Opcode::FunctionEnd,
+			Opcode::FunctionStart("f"),
+			Opcode::Label("other"),
+			Opcode::Return,
+			Opcode::FunctionEnd,
+		];
+		// Jump table is resolved when the function is defined.
+		assert_eq!(Program::new(&application).err(), Some(Error::LabelDoesNotExist("other")));
+
+		let application = wrap_into_main(&mut vec![
+			Opcode::Label("here"),
+			Opcode::Switch(OperandType::IntegerRegister(0), &["here"], "missing"),
+		]);
+		assert_eq!(Program::new(&application).err(), Some(Error::LabelDoesNotExist("missing")));
+
+		let application = wrap_into_main(&mut vec![
+			Opcode::Label("here"),
+			Opcode::Switch(OperandType::IntegerRegister(99), &["here"], "here"),
+		]);
+		let program = Program::new(&application).unwrap();
+		assert_eq!(vm.verify(&program), Err(Error::NoSuchIntegerRegister(32, 99)));
+	}
+
+	#[test]
+	fn check_no_label_jump_fail() {
+		let label_name = "v1";
+		let mut vm = ParallaxVm::default();
+		let application = wrap_into_main(&mut vec![
+			Opcode::JumpNotZero(label_name, OperandType::IntegerRegister(29)),
+		]);
+
+		let run_result = vm.run(application.as_slice());
+		assert_eq!(run_result, Err(Error::LabelDoesNotExist(label_name)));
+		assert_eq!(vm.get_integer_registers(), &[0; 32]);
+	}
+
+	#[test]
+	fn check_jump_not_taken() {
+		let mut vm = ParallaxVm::default();
+		let application = wrap_into_main(&mut vec![
+			Opcode::JumpZero("skip", OperandType::IntegerConstant(1)),
+			Opcode::Move(OperandType::IntegerRegister(0), OperandType::IntegerConstant(1)),
+			Opcode::Label("skip"),
+		]);
+
+		let run_result = vm.run(application.as_slice());
+		assert!(run_result.is_ok());
+		assert_eq!(vm.get_integer_registers()[0], 1);
+	}
+
+	#[test]
+	fn generic_test1() {
+		let mut vm = ParallaxVm::default();
+		let application: Vec<Opcode> = vec![
+			Opcode::FunctionStart("main"),
+			Opcode::Move(OperandType::IntegerRegister(1), OperandType::IntegerConstant(0x25)),
+			Opcode::Add(OperandType::IntegerRegister(2), OperandType::IntegerConstant(3), OperandType::IntegerConstant(5)),
+			Opcode::Jump("skip_next_opcode"),
+			Opcode::Add(OperandType::IntegerRegister(3), OperandType::IntegerRegister(2), OperandType::IntegerConstant(-1)),
+			Opcode::Label("skip_next_opcode"),
+			Opcode::Move(OperandType::IntegerRegister(10), OperandType::IntegerConstant(17)),
+			Opcode::Call("test1"),
+			Opcode::Sub(OperandType::IntegerRegister(2), OperandType::IntegerRegister(2), OperandType::IntegerConstant(6)),
+			Opcode::Return,
+			Opcode::FunctionEnd,
+			Opcode::FunctionStart("test1"),
+			Opcode::Add(OperandType::IntegerRegister(10), OperandType::IntegerConstant(10), OperandType::IntegerConstant(5)),
+			Opcode::JumpEqual("exit", OperandType::IntegerRegister(10), OperandType::IntegerConstant(15)),
+			Opcode::Move(OperandType::IntegerRegister(9), OperandType::IntegerConstant(0xFA)),
+			Opcode::Label("exit"),
+			Opcode::Return,
+			Opcode::FunctionEnd,
+		];
+		let run_result = vm.run(application.as_slice());
+		assert!(run_result.is_ok());
+		assert_eq!(vm.get_integer_registers(),
+			&[0, 37, 2, 0, 0, 0, 0, 0, 0, 0, 15, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0]);
+	}
+
+	#[test]
+	fn check_print() {
+		let io = MemoryIo::default();
+		let mut vm = ParallaxVm::with_io(Box::new(io.clone()));
+		let application = wrap_into_main(&mut vec![
+			Opcode::Move(OperandType::IntegerRegister(3), OperandType::IntegerConstant(-42)),
+			Opcode::PrintString("r3 = "),
+			Opcode::PrintInteger(OperandType::IntegerRegister(3)),
+			Opcode::PrintString("\n"),
+			Opcode::PrintInteger(OperandType::IntegerConstant(7)),
+		]);
+		let run_result = vm.run(application.as_slice());
+		assert!(run_result.is_ok());
+		assert_eq!(io.output(), "r3 = -42\n7");
+	}
+
+	#[test]
+	fn check_read() {
+		let io = MemoryIo::new(&[15, -3]);
+		let mut vm = ParallaxVm::with_io(Box::new(io.clone()));
+		let application = wrap_into_main(&mut vec![
+			Opcode::ReadInteger(OperandType::IntegerRegister(0)),
+			Opcode::ReadInteger(OperandType::IntegerRegister(1)),
+			Opcode::Add(OperandType::IntegerRegister(2), OperandType::IntegerRegister(0), OperandType::IntegerRegister(1)),
+			Opcode::PrintInteger(OperandType::IntegerRegister(2)),
+		]);
+		let run_result = vm.run(application.as_slice());
+		assert!(run_result.is_ok());
+		assert_eq!(io.output(), "12");
+		assert_eq!(&vm.get_integer_registers()[0..3], &[15, -3, 12]);
+	}
+
+	#[test]
+	fn check_read_fail() {
+		let mut vm = ParallaxVm::with_io(Box::new(MemoryIo::new(&[1])));
+		let application = wrap_into_main(&mut vec![
+			Opcode::ReadInteger(OperandType::IntegerRegister(0)),
+			Opcode::ReadInteger(OperandType::IntegerRegister(1)),
+		]);
+		let run_result = vm.run(application.as_slice());
+		assert_eq!(run_result, Err(Error::InputExhausted));
+		assert_eq!(&vm.get_integer_registers()[0..2], &[1, 0]);
+	}
+
+	#[test]
+	fn check_run_function() {
+		let mut vm = ParallaxVm::default();
+		let application = Program::new(&[
+			Opcode::FunctionStart("sum_and_diff"),
+			Opcode::Signature(2, 2),
+			Opcode::Add(OperandType::IntegerRegister(2), OperandType::IntegerRegister(0), OperandType::IntegerRegister(1)),
+			Opcode::Sub(OperandType::IntegerRegister(1), OperandType::IntegerRegister(0), OperandType::IntegerRegister(1)),
+			Opcode::Move(OperandType::IntegerRegister(0), OperandType::IntegerRegister(2)),
+			Opcode::Return,
+			Opcode::FunctionEnd,
+		]).unwrap();
+		assert_eq!(vm.run_function(&application, "sum_and_diff", &[10, 3]), Ok(vec![13, 7]));
+		assert_eq!(vm.run_function(&application, "sum_and_diff", &[-1, 1]), Ok(vec![0, -2]));
+		assert_eq!(vm.run_function(&application, "sum_and_diff", &[1]),
+			Err(Error::WrongNumberOfArguments("sum_and_diff", 2, 1)));
+		assert_eq!(vm.run_function(&application, "main", &[]), Err(Error::FunctionIsNotDefined("main")));
+	}
+
+	#[test]
+	fn check_signature_position_fail() {
+		let mut vm = ParallaxVm::default();
+		let application = wrap_into_main(&mut vec![
+			Opcode::Move(OperandType::IntegerRegister(0), OperandType::IntegerConstant(1)),
+			Opcode::Signature(1, 1),
+		]);
+		let run_result = vm.run(application.as_slice());
+		assert_eq!(run_result, Err(Error::BrokenFunctionDefinition("main")));
+	}
+
+	#[test]
+	fn check_register_count() {
+		let config = VmConfig{integer_registers: 8, floating_registers: 0, ..VmConfig::default()};
+		let mut vm = ParallaxVm::new(config, Box::new(StdIo));
+		let application = wrap_into_main(&mut vec![
+			Opcode::Move(OperandType::IntegerRegister(7), OperandType::IntegerConstant(1)),
+			Opcode::Move(OperandType::IntegerRegister(8), OperandType::IntegerConstant(2)),
+		]);
+		let program = Program::new(&application).unwrap();
+		assert_eq!(vm.verify(&program), Err(Error::NoSuchIntegerRegister(8, 8)));
+		assert_eq!(vm.run(application.as_slice()), Err(Error::NoSuchIntegerRegister(8, 8)));
+		assert_eq!(vm.get_integer_registers(), &[0, 0, 0, 0, 0, 0, 0, 1]);
+
+		let config = VmConfig{integer_registers: 256, floating_registers: 0, ..VmConfig::default()};
+		let mut vm = ParallaxVm::new(config, Box::new(StdIo));
+		assert!(vm.verify(&program).is_ok());
+		assert!(vm.run(application.as_slice()).is_ok());
+		assert_eq!(vm.get_integer_registers().len(), 256);
+		assert_eq!(vm.get_integer_registers()[8], 2);
+	}
+
+	#[test]
+	fn check_registers_header() {
+		let mut application = vec![Opcode::Registers(16, 0)];
+		application.append(&mut wrap_into_main(&mut vec![]));
+		let program = Program::new(&application).unwrap();
+		assert_eq!(program.registers(), Some((16, 0)));
+		let mut vm = ParallaxVm::new(VmConfig{integer_registers: 8, floating_registers: 0, ..VmConfig::default()}, Box::new(StdIo));
+		assert_eq!(vm.verify(&program), Err(Error::NoSuchIntegerRegister(8, 15)));
+		// Header is checked on run even without verification.
+		assert_eq!(vm.run_program(&program, "main"), Err(Error::NoSuchIntegerRegister(8, 15)));
+		assert_eq!(vm.run_function(&program, "main", &[]), Err(Error::NoSuchIntegerRegister(8, 15)));
+		let mut vm = ParallaxVm::new(VmConfig{integer_registers: 16, floating_registers: 0, ..VmConfig::default()}, Box::new(StdIo));
+		assert!(vm.verify(&program).is_ok());
+		assert!(vm.run_program(&program, "main").is_ok());
+
+		// Header after a function.
+		application.push(Opcode::Registers(16, 0));
+		assert_eq!(Program::new(&application).err(), Some(Error::BrokenProgramHeader));
+	}
+
+	#[cfg(feature = "float")]
+	/// Runs a single jump and reports if it was taken.
+	fn is_jump_taken(jump: Opcode) -> Result<bool, Error> {
+		let mut vm = ParallaxVm::default();
+		let application = wrap_into_main(&mut vec![
+			Opcode::Move(OperandType::FloatingRegister(0), OperandType::FloatingConstant(1.5)),
+			Opcode::Move(OperandType::FloatingRegister(1), OperandType::FloatingConstant(f64::NAN)),
+			jump,
+			Opcode::Move(OperandType::IntegerRegister(0), OperandType::IntegerConstant(1)),
+			Opcode::Label("taken"),
+		]);
+		vm.run(application.as_slice())?;
+		Ok(vm.get_integer_registers()[0] == 0)
+	}
+
+	#[cfg(feature = "float")]
+	#[test]
+	fn check_float_jumps() {
+		let f0 = OperandType::FloatingRegister(0);
+		let nan = OperandType::FloatingRegister(1);
+		let two = OperandType::FloatingConstant(2.0);
+		assert_eq!(is_jump_taken(Opcode::JumpBelow("taken", f0, two)), Ok(true));
+		assert_eq!(is_jump_taken(Opcode::JumpBelowEqual("taken", f0, f0)), Ok(true));
+		assert_eq!(is_jump_taken(Opcode::JumpAbove("taken", f0, two)), Ok(false));
+		assert_eq!(is_jump_taken(Opcode::JumpAboveEqual("taken", two, f0)), Ok(true));
+		assert_eq!(is_jump_taken(Opcode::JumpEqual("taken", f0, OperandType::FloatingConstant(1.5))), Ok(true));
+		assert_eq!(is_jump_taken(Opcode::JumpNotZero("taken", f0)), Ok(true));
+		assert_eq!(is_jump_taken(Opcode::JumpZero("taken", OperandType::FloatingConstant(-0.0))), Ok(true));
+		assert_eq!(is_jump_taken(Opcode::JumpUnordered("taken", f0, two)), Ok(false));
+
+		// NaN is unordered with everything, including itself.
+		assert_eq!(is_jump_taken(Opcode::JumpBelow("taken", nan, two)), Ok(false));
+		assert_eq!(is_jump_taken(Opcode::JumpAboveEqual("taken", nan, two)), Ok(false));
+		assert_eq!(is_jump_taken(Opcode::JumpEqual("taken", nan, nan)), Ok(false));
+		assert_eq!(is_jump_taken(Opcode::JumpNotEqual("taken", nan, nan)), Ok(true));
+		assert_eq!(is_jump_taken(Opcode::JumpUnordered("taken", nan, f0)), Ok(true));
+
+		assert_eq!(is_jump_taken(Opcode::JumpBelow("taken", f0, OperandType::IntegerConstant(2))),
+			Err(Error::UnsupportedOperand));
+	}
+
+	#[cfg(feature = "float")]
+	#[test]
+	fn check_mixed_comparison_verify_fail() {
+		let vm = ParallaxVm::default();
+		let application = wrap_into_main(&mut vec![
+			Opcode::JumpAbove("exit", OperandType::FloatingRegister(3), OperandType::IntegerRegister(3)),
+			Opcode::Label("exit"),
+		]);
+		let program = Program::new(&application).unwrap();
+		assert_eq!(vm.verify(&program), Err(Error::UnsupportedOperand));
+	}
+
+	#[cfg(feature = "float")]
+	#[test]
+	fn check_float_math() {
+		let mut vm = ParallaxVm::default();
+		let f = OperandType::FloatingRegister;
+		let c = OperandType::FloatingConstant;
+		let application = wrap_into_main(&mut vec![
+			Opcode::Mul(f(0), c(1.5), c(-4.0)),
+			Opcode::Div(f(1), f(0), c(0.0)),
+			Opcode::Sqrt(f(2), c(2.25)),
+			Opcode::Abs(f(3), f(0)),
+			Opcode::Neg(f(4), f(3)),
+			Opcode::Floor(f(5), c(-2.5)),
+			Opcode::Ceil(f(6), c(-2.5)),
+			Opcode::Round(f(7), c(-2.5)),
+			Opcode::Min(f(8), f(0), c(f64::NAN)),
+			Opcode::Max(f(9), f(0), f(3)),
+			Opcode::FusedMulAdd(f(10), c(0.1), c(10.0), c(-1.0)),
+			Opcode::Sin(f(11), c(0.0)),
+			Opcode::Cos(f(12), c(0.0)),
+			Opcode::Exp(f(13), c(0.0)),
+			Opcode::Ln(f(14), c(1.0)),
+			Opcode::Pow(f(15), c(2.0), c(10.0)),
+			Opcode::Sqrt(f(16), c(-1.0)),
+		]);
+		let run_result = vm.run(application.as_slice());
+		assert!(run_result.is_ok());
+		assert_eq!(&vm.get_floating_registers()[..11], &[-6.0, f64::NEG_INFINITY, 1.5, 6.0, -6.0, -3.0, -2.0, -3.0,
+			-6.0, 6.0, 0.1f64.mul_add(10.0, -1.0)]);
+		// Platform math library is not required to be exactly rounded.
+		for (&value, &expected) in vm.get_floating_registers()[11..16].iter().zip(&[0.0, 1.0, 1.0, 0.0, 1024.0]) {
+			assert!((value - expected).abs() <= 1e-12 * expected.abs().max(1.0));
+		}
+		assert!(vm.get_floating_registers()[16].is_nan());
+	}
+
+	#[cfg(feature = "float")]
+	#[test]
+	fn check_float_math_verify_fail() {
+		let vm = ParallaxVm::default();
+		let application = wrap_into_main(&mut vec![
+			Opcode::Sqrt(OperandType::FloatingRegister(0), OperandType::IntegerRegister(0)),
+		]);
+		let program = Program::new(&application).unwrap();
+		assert_eq!(vm.verify(&program), Err(Error::UnsupportedOperand));
+	}
+
+	#[cfg(feature = "float")]
+	#[test]
+	fn check_float_move_if() {
+		let mut vm = ParallaxVm::default();
+		let application = wrap_into_main(&mut vec![
+			Opcode::Move(OperandType::FloatingRegister(0), OperandType::FloatingConstant(f64::NAN)),
+			Opcode::MoveIfUnordered(OperandType::IntegerRegister(0), OperandType::IntegerConstant(1),
+				OperandType::FloatingRegister(0), OperandType::FloatingConstant(0.0)),
+			Opcode::MoveIfNotEqual(OperandType::IntegerRegister(1), OperandType::IntegerConstant(1),
+				OperandType::FloatingRegister(0), OperandType::FloatingRegister(0)),
+			Opcode::MoveIfAboveEqual(OperandType::IntegerRegister(2), OperandType::IntegerConstant(1),
+				OperandType::FloatingRegister(0), OperandType::FloatingConstant(0.0)),
+			Opcode::Select(OperandType::FloatingRegister(1), OperandType::FloatingConstant(-0.0),
+				OperandType::FloatingConstant(1.0), OperandType::FloatingConstant(2.0)),
+		]);
+		let run_result = vm.run(application.as_slice());
+		assert!(run_result.is_ok());
+		assert_eq!(&vm.get_integer_registers()[..3], &[1, 1, 0]);
+		assert_eq!(vm.get_floating_registers()[1], 2.0);
 	}
 }
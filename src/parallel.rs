@@ -0,0 +1,86 @@
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::thread;
+
+use error::Error;
+use parallax_vm::ParallaxVm;
+use program::Program;
+
+/// A function call to run: function name and its arguments.
+pub type Job = (&'static str, Vec<i64>);
+
+/// Results of a function call or an error it failed with.
+pub type JobResult = Result<Vec<i64>, Error>;
+
+/// Run independent function calls of a program on a pool of OS threads.
+/// Every thread creates its own VM with `new_vm` and takes jobs one by one,
+/// the program is shared. VM is reset before every job, so jobs can't see each other
+/// through registers or memory and results don't depend on distribution of jobs.
+/// Results are in the order of jobs. Zero threads means one per available core.
+pub fn run_parallel<F>(program: &Program, jobs: &[Job], threads: usize, new_vm: F) -> Vec<JobResult>
+		where F: Fn() -> ParallaxVm + Sync {
+	let threads = if threads == 0 {
+		thread::available_parallelism().map_or(1, |n| n.get())
+	} else {
+		threads
+	};
+	let next_job = AtomicUsize::new(0);
+	let results: Mutex<Vec<Option<JobResult>>> = Mutex::new((0..jobs.len()).map(|_| None).collect());
+	thread::scope(|scope| {
+		for _ in 0..threads.min(jobs.len()) {
+			scope.spawn(|| {
+				let mut vm = new_vm();
+				loop {
+					let index = next_job.fetch_add(1, Ordering::Relaxed);
+					let &(name, ref args) = match jobs.get(index) {
+						Some(job) => job,
+						None => break,
+					};
+					vm.reset();
+					let result = vm.run_function(program, name, args);
+					results.lock().unwrap()[index] = Some(result);
+				}
+			});
+		}
+	});
+	results.into_inner().unwrap().into_iter().map(|result| result.unwrap()).collect()
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use opcode::Opcode;
+	use operand::OperandType;
+
+	fn is_send<T: Send>() {}
+	fn is_sync<T: Sync>() {}
+
+	#[test]
+	fn check_thread_safety() {
+		is_send::<ParallaxVm>();
+		is_send::<Program>();
+		is_sync::<Program>();
+	}
+
+	#[test]
+	fn check_run_parallel() {
+		let application = vec![
+			Opcode::FunctionStart("square"),
+			Opcode::Signature(1, 1),
+			Opcode::Add(OperandType::IntegerRegister(1), OperandType::IntegerRegister(1), OperandType::IntegerConstant(1)),
+			Opcode::Mul(OperandType::IntegerRegister(0), OperandType::IntegerRegister(0), OperandType::IntegerRegister(0)),
+			Opcode::Add(OperandType::IntegerRegister(0), OperandType::IntegerRegister(0), OperandType::IntegerRegister(1)),
+			Opcode::Return,
+			Opcode::FunctionEnd,
+		];
+		let program = Program::new(&application).unwrap();
+		let mut jobs: Vec<Job> = (0..100).map(|i| ("square", vec![i])).collect();
+		jobs.push(("cube", vec![2]));
+		let results = run_parallel(&program, &jobs, 4, ParallaxVm::default);
+		// r1 is zero at start of every job.
+		for i in 0..100 {
+			assert_eq!(results[i as usize], Ok(vec![i * i + 1]));
+		}
+		assert_eq!(results[100], Err(Error::FunctionIsNotDefined("cube")));
+	}
+}
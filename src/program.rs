@@ -0,0 +1,553 @@
+use std::collections::HashMap;
+
+use error::Error;
+use function::Function;
+use memory::Memory;
+use opcode::{Argument, Opcode};
+use operand::OperandType;
+use parallax_vm::VmConfig;
+
+/// Initialized data, placed into VM memory before a run.
+#[derive(Debug)]
+pub struct DataBlock {
+	pub name: &'static str,
+	pub address: usize,
+	pub bytes: Vec<u8>,
+	pub read_only: bool,
+}
+
+/// Data blocks are aligned to this number of bytes.
+const DATA_ALIGNMENT: usize = 8;
+
+/// Function handle is an index in the function table in the lower bits and this tag in the upper ones.
+const FUNCTION_HANDLE_TAG: i64 = 0x504C_5846 << 32;
+const FUNCTION_HANDLE_INDEX: i64 = 0xFFFF_FFFF;
+
+/// Loaded application: list of opcodes and all functions defined in it.
+/// Program doesn't depend on VM state, so it can be loaded once and run many times.
+#[derive(Debug)]
+pub struct Program {
+	opcodes: Vec<Opcode>,
+	functions: HashMap<&'static str, Function>,
+	/// Function names in order of definition, index is a function handle.
+	function_table: Vec<&'static str>,
+	/// Number of integer and floating registers, declared in program header.
+	registers: Option<(usize, usize)>,
+	data: Vec<DataBlock>,
+}
+
+impl Program {
+	/// Collect all functions and data defined in a list of opcodes.
+	/// Operands, that refer to data blocks, are replaced by their addresses.
+	pub fn new(opcodes: &[Opcode]) -> Result<Program, Error> {
+		let mut functions: HashMap<&'static str, Function> = HashMap::new();
+		let mut function_table: Vec<&'static str> = Vec::new();
+		let mut registers: Option<(usize, usize)> = None;
+		let data: Vec<DataBlock> = Program::layout_data(opcodes)?;
+
+		for (i, &op) in opcodes.iter().enumerate() {
+			if let Opcode::FunctionStart(name) = op {
+				if functions.contains_key(name) {
+					return Err(Error::FunctionIsAlreadyDefined(name));
+				}
+				let current_func: Function = Function::define(name, i, &opcodes[i..])?;
+				functions.insert(name, current_func);
+				function_table.push(name);
+			}
+			if let Opcode::Registers(integer, floating) = op {
+				// Header must precede all functions and can't be repeated.
+				if !functions.is_empty() || registers.is_some() {
+					return Err(Error::BrokenProgramHeader);
+				}
+				registers = Some((integer, floating));
+			}
+		}
+
+		let symbols: HashMap<&'static str, usize> = data.iter().map(|block| (block.name, block.address)).collect();
+		let opcodes: Vec<Opcode> = opcodes.iter()
+				.map(|opcode| Program::link(opcode, &symbols))
+				.collect::<Result<Vec<Opcode>, Error>>()?;
+
+		Ok(Program {
+			opcodes,
+			functions,
+			function_table,
+			registers,
+			data,
+		})
+	}
+
+	/// Place data blocks one after another from the beginning of memory.
+	fn layout_data(opcodes: &[Opcode]) -> Result<Vec<DataBlock>, Error> {
+		let mut data: Vec<DataBlock> = Vec::new();
+		let mut address: usize = 0;
+		for opcode in opcodes {
+			let (name, format, values, read_only) = match *opcode {
+				Opcode::Data(name, format, values)         => (name, format, values, false),
+				Opcode::ReadOnlyData(name, format, values) => (name, format, values, true),
+				_ => continue,
+			};
+			if data.iter().any(|block| block.name == name) {
+				return Err(Error::SymbolIsAlreadyDefined(name));
+			}
+			let mut memory = Memory::new(values.len() * format.width());
+			for (i, &value) in values.iter().enumerate() {
+				memory.store(i * format.width(), format, value)?;
+			}
+			let bytes: Vec<u8> = memory.bytes().to_vec();
+			let size = bytes.len();
+			data.push(DataBlock{name, address, bytes, read_only});
+			address += size.div_ceil(DATA_ALIGNMENT) * DATA_ALIGNMENT;
+		}
+		Ok(data)
+	}
+
+	/// Replace symbols in operands of an opcode with addresses.
+	fn link(opcode: &Opcode, symbols: &HashMap<&'static str, usize>) -> Result<Opcode, Error> {
+		let (mnemonic, args) = opcode.to_parts();
+		if !args.iter().any(has_symbols) {
+			return Ok(*opcode);
+		}
+		let linked = args.into_iter()
+				.map(|arg| link_argument(arg, symbols))
+				.collect::<Result<Vec<Argument>, Error>>()?;
+		Opcode::from_parts(mnemonic, &linked)
+	}
+
+	/// All opcodes of the program.
+	pub fn opcodes(&self) -> &[Opcode] {
+		&self.opcodes
+	}
+
+	/// All functions of the program.
+	pub fn functions(&self) -> &HashMap<&'static str, Function> {
+		&self.functions
+	}
+
+	#[cfg(test)]
+	/// Change functions for test purposes, e.g. their frame sizes
+	pub fn functions_mut(&mut self) -> &mut HashMap<&'static str, Function> {
+		&mut self.functions
+	}
+
+	/// Opaque handle of a function, that can be called with CallIndirect.
+	/// Index in the function table is tagged, so that zero or a small number is not a valid handle.
+	pub fn function_handle(&self, name: &str) -> Option<i64> {
+		self.function_table.iter().position(|&n| n == name).map(|i| FUNCTION_HANDLE_TAG | i as i64)
+	}
+
+	/// Function referred by a handle, None if the handle is invalid.
+	pub fn function_by_handle(&self, handle: i64) -> Option<&Function> {
+		if handle & !FUNCTION_HANDLE_INDEX != FUNCTION_HANDLE_TAG {
+			return None;
+		}
+		let name: &str = self.function_table.get((handle & FUNCTION_HANDLE_INDEX) as usize)?;
+		self.functions.get(name)
+	}
+
+	/// Data blocks in the order of their addresses.
+	pub fn data(&self) -> &[DataBlock] {
+		&self.data
+	}
+
+	/// Number of bytes at the beginning of memory occupied by data blocks.
+	pub fn data_size(&self) -> usize {
+		self.data.last().map_or(0, |block| block.address + block.bytes.len())
+	}
+
+	/// Number of integer and floating registers the program needs, if declared.
+	pub fn registers(&self) -> Option<(usize, usize)> {
+		self.registers
+	}
+
+	/// Check that data and registers, declared in the header, fit into a VM.
+	/// Unlike the rest of verification it is done on every run.
+	pub fn check_config(&self, config: &VmConfig) -> Result<(), Error> {
+		if self.data_size() > config.memory_size {
+			return Err(Error::MemoryOutOfBounds(0, self.data_size()));
+		}
+		if let Some((integer, floating)) = self.registers {
+			if integer > config.integer_registers {
+				return Err(Error::NoSuchIntegerRegister(config.integer_registers, integer - 1));
+			}
+			if floating > config.floating_registers {
+				return Err(Error::NoSuchFloatingRegister(config.floating_registers, floating - 1));
+			}
+		}
+		Ok(())
+	}
+
+	/// Static checks, that can be done without running a program:
+	/// labels and functions exist, registers are in range and destinations are writable.
+	/// Verification is not required for run, runtime does the same checks lazily.
+	pub fn verify(&self, config: &VmConfig) -> Result<(), Error> {
+		let verifier = Verifier {
+			integer_registers: config.integer_registers,
+			#[cfg(feature = "float")]
+			floating_registers: config.floating_registers,
+			memory_size: config.memory_size,
+		};
+		self.check_config(config)?;
+		// Sort functions to report errors in a stable order.
+		let mut functions: Vec<&Function> = self.functions.values().collect();
+		functions.sort_by_key(|f| f.opcodes_range.start);
+		for function in functions {
+			for index in function.opcodes_range.clone() {
+				let opcode: &Opcode = &self.opcodes[index];
+				verifier.check_opcode(opcode, function, &self.functions)?;
+				if let Opcode::TailCall(_) = *opcode {
+					verifier.check_tail_position(function, index, &self.opcodes)?;
+				}
+			}
+		}
+		Ok(())
+	}
+}
+
+fn has_symbols(arg: &Argument) -> bool {
+	match *arg {
+		Argument::Operand(OperandType::Symbol(_)) | Argument::Operand(OperandType::MemorySymbol(..)) => true,
+		Argument::List(ref items) => items.iter().any(has_symbols),
+		_ => false,
+	}
+}
+
+/// Symbols in lists are replaced too, e.g. in arguments of Spawn.
+fn link_argument(arg: Argument, symbols: &HashMap<&'static str, usize>) -> Result<Argument, Error> {
+	let address = |name: &'static str| symbols.get(name).cloned().ok_or(Error::SymbolIsNotDefined(name));
+	Ok(match arg {
+		Argument::Operand(OperandType::Symbol(name)) =>
+			Argument::Operand(OperandType::IntegerConstant(address(name)? as i64)),
+		Argument::Operand(OperandType::MemorySymbol(name, displacement)) =>
+			Argument::Operand(OperandType::Memory((address(name)? as i64).wrapping_add(displacement) as usize)),
+		Argument::List(items) => Argument::List(items.into_iter()
+				.map(|item| link_argument(item, symbols))
+				.collect::<Result<Vec<Argument>, Error>>()?),
+		_ => arg,
+	})
+}
+
+struct Verifier {
+	integer_registers: usize,
+	#[cfg(feature = "float")]
+	floating_registers: usize,
+	memory_size: usize,
+}
+
+impl Verifier {
+	fn check_opcode(&self, opcode: &Opcode, current_func: &Function, functions: &HashMap<&'static str, Function>)
+			-> Result<(), Error> {
+		match *opcode {
+			Opcode::FunctionStart(name) => Err(Error::BrokenFunctionDefinition(name)),
+			Opcode::Registers(..) | Opcode::Data(..) | Opcode::ReadOnlyData(..) =>
+				Err(Error::BrokenFunctionDefinition(current_func.name)),
+			Opcode::FunctionEnd => Err(Error::OpcodeMustBeUnreachable),
+			Opcode::Call(name) | Opcode::TailCall(name) => {
+				functions.get(name).ok_or(Error::FunctionIsNotDefined(name))?;
+				Ok(())
+			},
+			Opcode::CallIndirect(handle) => {
+				self.check_source(handle)?;
+				self.check_integer(&[handle])
+			},
+			Opcode::FuncRef(dst, name) => {
+				functions.get(name).ok_or(Error::FunctionIsNotDefined(name))?;
+				self.check_destination(dst)?;
+				self.check_integer(&[dst])
+			},
+			Opcode::Return | Opcode::Label(_) | Opcode::PrintString(_) => Ok(()),
+			Opcode::Signature(arguments, results) => {
+				let count = arguments.max(results);
+				if count > self.integer_registers {
+					return Err(Error::NoSuchIntegerRegister(self.integer_registers, count - 1));
+				}
+				Ok(())
+			},
+			Opcode::Jump(name) => self.check_label(current_func, name),
+			Opcode::JumpZero(name, a) | Opcode::JumpNotZero(name, a) => {
+				self.check_label(current_func, name)?;
+				self.check_source(a)
+			},
+			Opcode::JumpBelow(name, a, b) | Opcode::JumpBelowEqual(name, a, b) |
+			Opcode::JumpAbove(name, a, b) | Opcode::JumpAboveEqual(name, a, b) |
+			Opcode::JumpEqual(name, a, b) | Opcode::JumpNotEqual(name, a, b) |
+			Opcode::JumpUnordered(name, a, b) => {
+				self.check_label(current_func, name)?;
+				self.check_source(a)?;
+				self.check_source(b)?;
+				// Integers and floats are not comparable.
+				self.check_same_type(&[a, b])
+			},
+			Opcode::JumpBelowUnsigned(name, a, b) | Opcode::JumpBelowEqualUnsigned(name, a, b) |
+			Opcode::JumpAboveUnsigned(name, a, b) | Opcode::JumpAboveEqualUnsigned(name, a, b) => {
+				self.check_label(current_func, name)?;
+				self.check_source(a)?;
+				self.check_source(b)?;
+				self.check_integer(&[a, b])
+			},
+			Opcode::Compare(a, b) => {
+				self.check_source(a)?;
+				self.check_source(b)?;
+				self.check_integer(&[a, b])
+			},
+			Opcode::JumpIf(_condition, name) => self.check_label(current_func, name),
+			Opcode::Switch(index, labels, default) => {
+				for &name in labels {
+					self.check_label(current_func, name)?;
+				}
+				self.check_label(current_func, default)?;
+				self.check_source(index)?;
+				self.check_integer(&[index])
+			},
+			Opcode::Protect(start, end, handler) => {
+				self.check_label(current_func, start)?;
+				self.check_label(current_func, end)?;
+				self.check_label(current_func, handler)
+			},
+			Opcode::Throw(code) => {
+				self.check_source(code)?;
+				self.check_integer(&[code])
+			},
+			Opcode::Catch(dst) => {
+				self.check_destination(dst)?;
+				self.check_integer(&[dst])
+			},
+			Opcode::Move(dst, src) => {
+				self.check_destination(dst)?;
+				self.check_source(src)
+			},
+			Opcode::Load(dst, address, format) => {
+				self.check_destination(dst)?;
+				self.check_access(address, format.width())?;
+				self.check_integer(&[dst, address])
+			},
+			Opcode::Store(address, src, format) => {
+				self.check_access(address, format.width())?;
+				self.check_source(src)?;
+				self.check_integer(&[address, src])
+			},
+			Opcode::Select(dst, condition, a, b) => {
+				self.check_destination(dst)?;
+				self.check_source(condition)?;
+				self.check_source(a)?;
+				self.check_source(b)?;
+				self.check_same_type(&[dst, a, b])
+			},
+			Opcode::MoveIfBelow(dst, src, a, b) | Opcode::MoveIfBelowEqual(dst, src, a, b) |
+			Opcode::MoveIfAbove(dst, src, a, b) | Opcode::MoveIfAboveEqual(dst, src, a, b) |
+			Opcode::MoveIfEqual(dst, src, a, b) | Opcode::MoveIfNotEqual(dst, src, a, b) |
+			Opcode::MoveIfUnordered(dst, src, a, b) => {
+				self.check_destination(dst)?;
+				self.check_source(src)?;
+				self.check_source(a)?;
+				self.check_source(b)?;
+				self.check_same_type(&[dst, src])?;
+				self.check_same_type(&[a, b])
+			},
+			Opcode::Add(dst, a, b) | Opcode::Sub(dst, a, b) |
+			Opcode::Mul(dst, a, b) | Opcode::Div(dst, a, b) |
+			Opcode::Min(dst, a, b) | Opcode::Max(dst, a, b) => {
+				self.check_destination(dst)?;
+				self.check_source(a)?;
+				self.check_source(b)?;
+				self.check_same_type(&[dst, a, b])
+			},
+			Opcode::AddCarry(dst, a, b) | Opcode::SubBorrow(dst, a, b) => {
+				self.check_destination(dst)?;
+				self.check_source(a)?;
+				self.check_source(b)?;
+				self.check_integer(&[dst, a, b])
+			},
+			Opcode::Abs(dst, src) | Opcode::Neg(dst, src) => {
+				self.check_destination(dst)?;
+				self.check_source(src)?;
+				self.check_same_type(&[dst, src])
+			},
+			#[cfg(feature = "float")]
+			Opcode::Sqrt(dst, src) | Opcode::Floor(dst, src) | Opcode::Ceil(dst, src) |
+			Opcode::Round(dst, src) | Opcode::Sin(dst, src) | Opcode::Cos(dst, src) |
+			Opcode::Exp(dst, src) | Opcode::Ln(dst, src) => {
+				self.check_destination(dst)?;
+				self.check_source(src)?;
+				self.check_floating(&[dst, src])
+			},
+			#[cfg(feature = "float")]
+			Opcode::Pow(dst, a, b) => {
+				self.check_destination(dst)?;
+				self.check_source(a)?;
+				self.check_source(b)?;
+				self.check_floating(&[dst, a, b])
+			},
+			#[cfg(feature = "float")]
+			Opcode::FusedMulAdd(dst, a, b, c) => {
+				self.check_destination(dst)?;
+				self.check_source(a)?;
+				self.check_source(b)?;
+				self.check_source(c)?;
+				self.check_floating(&[dst, a, b, c])
+			},
+			Opcode::Alloc(dst, size) => {
+				self.check_destination(dst)?;
+				self.check_source(size)?;
+				self.check_integer(&[dst, size])
+			},
+			Opcode::Free(ptr) => {
+				self.check_source(ptr)?;
+				self.check_integer(&[ptr])
+			},
+			Opcode::Realloc(dst, ptr, size) => {
+				self.check_destination(dst)?;
+				self.check_source(ptr)?;
+				self.check_source(size)?;
+				self.check_integer(&[dst, ptr, size])
+			},
+			Opcode::Spawn(dst, name, args) | Opcode::CoroutineNew(dst, name, args) => {
+				let function = functions.get(name).ok_or(Error::FunctionIsNotDefined(name))?;
+				if args.len() != function.arguments {
+					return Err(Error::WrongNumberOfArguments(name, function.arguments, args.len()));
+				}
+				self.check_destination(dst)?;
+				for &arg in args {
+					self.check_source(arg)?;
+				}
+				self.check_integer(&[dst])?;
+				self.check_integer(args)
+			},
+			Opcode::Yield => Ok(()),
+			Opcode::Resume(status, dst, handle) => {
+				self.check_destination(status)?;
+				self.check_destination(dst)?;
+				self.check_source(handle)?;
+				self.check_integer(&[status, handle])
+			},
+			Opcode::YieldValue(src) => self.check_source(src),
+			Opcode::Join(handle) | Opcode::Close(handle) => {
+				self.check_source(handle)?;
+				self.check_integer(&[handle])
+			},
+			Opcode::ChanNew(dst, src) | Opcode::Recv(dst, src) => {
+				self.check_destination(dst)?;
+				self.check_source(src)?;
+				self.check_integer(&[dst, src])
+			},
+			Opcode::Send(channel, src) => {
+				self.check_source(channel)?;
+				self.check_source(src)?;
+				self.check_integer(&[channel, src])
+			},
+			Opcode::TrySend(status, channel, src) => {
+				self.check_destination(status)?;
+				self.check_source(channel)?;
+				self.check_source(src)?;
+				self.check_integer(&[status, channel, src])
+			},
+			Opcode::TryRecv(status, dst, channel) => {
+				self.check_destination(status)?;
+				self.check_destination(dst)?;
+				self.check_source(channel)?;
+				self.check_integer(&[status, dst, channel])
+			},
+			Opcode::AtomicLoad(dst, address) => {
+				self.check_destination(dst)?;
+				self.check_source(address)?;
+				self.check_integer(&[dst, address])
+			},
+			Opcode::AtomicStore(address, src) => {
+				self.check_source(address)?;
+				self.check_source(src)?;
+				self.check_integer(&[address, src])
+			},
+			Opcode::CompareExchange(dst, address, expected, new) => {
+				self.check_destination(dst)?;
+				self.check_source(address)?;
+				self.check_source(expected)?;
+				self.check_source(new)?;
+				self.check_integer(&[dst, address, expected, new])
+			},
+			Opcode::FetchAdd(dst, address, src) | Opcode::FetchAnd(dst, address, src) |
+			Opcode::FetchOr(dst, address, src) | Opcode::FetchXor(dst, address, src) => {
+				self.check_destination(dst)?;
+				self.check_source(address)?;
+				self.check_source(src)?;
+				self.check_integer(&[dst, address, src])
+			},
+			Opcode::Fence => Ok(()),
+			Opcode::PrintInteger(src) => self.check_source(src),
+			Opcode::ReadInteger(dst) => self.check_destination(dst),
+		}
+	}
+
+	fn check_tail_position(&self, current_func: &Function, index: usize, opcodes: &[Opcode]) -> Result<(), Error> {
+		if current_func.is_tail_position(index, opcodes) {
+			Ok(())
+		} else {
+			Err(Error::TailCallNotInTailPosition(current_func.name))
+		}
+	}
+
+	fn check_label(&self, current_func: &Function, label_name: &'static str) -> Result<(), Error> {
+		let label: usize = *current_func.labels.get(label_name).ok_or(Error::LabelDoesNotExist(label_name))?;
+		if current_func.is_opcode_in_range(label) {
+			Ok(())
+		} else {
+			Err(Error::RestrictedJumpOutOfScope(current_func.name))
+		}
+	}
+
+	fn check_source(&self, operand: OperandType) -> Result<(), Error> {
+		self.check_access(operand, 8)
+	}
+
+	/// Memory operand is checked for an access of a given width, all others like a source.
+	fn check_access(&self, operand: OperandType, width: usize) -> Result<(), Error> {
+		match operand {
+			OperandType::IntegerRegister(n) if n >= self.integer_registers =>
+				Err(Error::NoSuchIntegerRegister(self.integer_registers, n)),
+			#[cfg(feature = "float")]
+			OperandType::FloatingRegister(n) if n >= self.floating_registers =>
+				Err(Error::NoSuchFloatingRegister(self.floating_registers, n)),
+			OperandType::Memory(address) if address.checked_add(width).is_none_or(|end| end > self.memory_size) =>
+				Err(Error::MemoryOutOfBounds(address, width)),
+			OperandType::MemoryBase(base, _) if base >= self.integer_registers =>
+				Err(Error::NoSuchIntegerRegister(self.integer_registers, base)),
+			OperandType::MemoryIndex(base, index, _, _) if base.max(index) >= self.integer_registers =>
+				Err(Error::NoSuchIntegerRegister(self.integer_registers, base.max(index))),
+			_ => Ok(()),
+		}
+	}
+
+	/// Integer and floating operands can't be mixed.
+	fn check_same_type(&self, operands: &[OperandType]) -> Result<(), Error> {
+		if operands.iter().all(|x| x.is_floating() == operands[0].is_floating()) {
+			Ok(())
+		} else {
+			Err(Error::UnsupportedOperand)
+		}
+	}
+
+	fn check_integer(&self, operands: &[OperandType]) -> Result<(), Error> {
+		if operands.iter().any(|x| x.is_floating()) {
+			Err(Error::UnsupportedOperand)
+		} else {
+			Ok(())
+		}
+	}
+
+	#[cfg(feature = "float")]
+	fn check_floating(&self, operands: &[OperandType]) -> Result<(), Error> {
+		if operands.iter().all(|x| x.is_floating()) {
+			Ok(())
+		} else {
+			Err(Error::UnsupportedOperand)
+		}
+	}
+
+	fn check_destination(&self, operand: OperandType) -> Result<(), Error> {
+		match operand {
+			OperandType::IntegerConstant(_) => Err(Error::UnsupportedOperation),
+			#[cfg(feature = "float")]
+			OperandType::FloatingConstant(_) => Err(Error::UnsupportedOperation),
+			_ => self.check_source(operand),
+		}
+	}
+}
@@ -0,0 +1,141 @@
+extern crate parallax;
+
+use parallax::{Error, Function, MemoryIo, Opcode, OperandType, ParallaxVm, Program, RunOptions};
+use parallax::{assemble, from_bytecode, to_bytecode};
+
+#[test]
+fn run_program_with_calls() {
+	let io = MemoryIo::default();
+	let mut vm = ParallaxVm::with_io(Box::new(io.clone()));
+	let application: Vec<Opcode> = vec![
+		Opcode::FunctionStart("main"),
+		Opcode::Move(OperandType::IntegerRegister(0), OperandType::IntegerConstant(3)),
+		Opcode::Label("loop"),
+		Opcode::Call("print_and_decrement"),
+		Opcode::JumpNotZero("loop", OperandType::IntegerRegister(0)),
+		Opcode::Return,
+		Opcode::FunctionEnd,
+		Opcode::FunctionStart("print_and_decrement"),
+		Opcode::PrintInteger(OperandType::IntegerRegister(0)),
+		Opcode::PrintString(" "),
+		Opcode::Sub(OperandType::IntegerRegister(0), OperandType::IntegerRegister(0), OperandType::IntegerConstant(1)),
+		Opcode::Return,
+		Opcode::FunctionEnd,
+	];
+	assert!(vm.run(application.as_slice()).is_ok());
+	assert_eq!(io.output(), "3 2 1 ");
+	assert_eq!(vm.get_integer_registers()[0], 0);
+}
+
+#[test]
+fn define_function_reports_bounds() {
+	let application: Vec<Opcode> = vec![
+		Opcode::FunctionStart("main"),
+		Opcode::Label("start"),
+		Opcode::Return,
+		Opcode::FunctionEnd,
+	];
+	let function = Function::define("main", 0, application.as_slice()).unwrap();
+	assert_eq!(function.name, "main");
+	assert_eq!(function.opcodes_range, 1..3);
+	assert_eq!(function.labels.get("start"), Some(&1));
+}
+
+#[test]
+fn undefined_function_is_reported() {
+	let mut vm = ParallaxVm::default();
+	let application: Vec<Opcode> = vec![
+		Opcode::FunctionStart("main"),
+		Opcode::Call("missing"),
+		Opcode::Return,
+		Opcode::FunctionEnd,
+	];
+	assert_eq!(vm.run(application.as_slice()), Err(Error::FunctionIsNotDefined("missing")));
+}
+
+#[test]
+fn verify_without_running() {
+	let vm = ParallaxVm::default();
+	let application = assemble("
+		function main
+			move r1, 5
+			jump_equal missing, r1, 5
+			return
+		end_function
+	").unwrap();
+	let program = Program::new(&application).unwrap();
+	assert_eq!(vm.verify(&program), Err(Error::LabelDoesNotExist("missing")));
+
+	let application = assemble("
+		function main
+			add r40, r1, 5
+			return
+		end_function
+	").unwrap();
+	let program = Program::new(&application).unwrap();
+	assert_eq!(vm.verify(&program), Err(Error::NoSuchIntegerRegister(32, 40)));
+}
+
+#[test]
+fn run_custom_entry_with_fuel() {
+	let mut vm = ParallaxVm::default();
+	let application = from_bytecode(&to_bytecode(&assemble("
+		function endless
+			label again
+			add r0, r0, 1
+			jump again
+		end_function
+	").unwrap())).unwrap();
+	let program = Program::new(&application).unwrap();
+	vm.set_fuel_limit(Some(10));
+	assert_eq!(vm.run_program(&program, "endless"), Err(Error::FuelExhausted(10)));
+	assert_eq!(vm.get_integer_registers()[0], 3);
+}
+
+#[test]
+fn back_to_back_runs() {
+	let mut vm = ParallaxVm::default();
+	let application = Program::new(&assemble("
+		function main
+			add r0, r0, 1
+			add [0], [0], 1
+			call check
+			return
+		end_function
+		function check
+			jump_below ok, r0, 2
+			call missing
+			label ok
+			return
+		end_function
+	").unwrap()).unwrap();
+
+	// Registers and memory are preserved by default.
+	assert!(vm.run_program(&application, "main").is_ok());
+	assert_eq!((vm.get_integer_registers()[0], vm.get_memory()[0]), (1, 1));
+	// Failed run leaves registers and memory as they were at the moment of failure.
+	assert_eq!(vm.run_program(&application, "main"), Err(Error::FunctionIsNotDefined("missing")));
+	assert_eq!((vm.get_integer_registers()[0], vm.get_memory()[0]), (2, 2));
+
+	// Call stack of the failed run doesn't leak into the next one.
+	vm.reset();
+	assert_eq!(vm.get_integer_registers(), &[0; 32]);
+	assert_eq!(vm.get_memory()[0], 0);
+	assert!(vm.run_program(&application, "main").is_ok());
+	assert_eq!((vm.get_integer_registers()[0], vm.get_memory()[0]), (1, 1));
+
+	vm.set_run_options(RunOptions{clear_registers: true, clear_memory: false});
+	assert!(vm.run_program(&application, "main").is_ok());
+	assert!(vm.run_program(&application, "main").is_ok());
+	assert_eq!((vm.get_integer_registers()[0], vm.get_memory()[0]), (1, 3));
+
+	vm.set_run_options(RunOptions{clear_registers: true, clear_memory: true});
+	assert!(vm.run_program(&application, "main").is_ok());
+	assert!(vm.run_program(&application, "main").is_ok());
+	assert_eq!((vm.get_integer_registers()[0], vm.get_memory()[0]), (1, 1));
+
+	// Only memory is cleared, so the second run sees r0 = 1 and fails.
+	vm.set_run_options(RunOptions{clear_registers: false, clear_memory: true});
+	assert_eq!(vm.run_program(&application, "main"), Err(Error::FunctionIsNotDefined("missing")));
+	assert_eq!((vm.get_integer_registers()[0], vm.get_memory()[0]), (2, 1));
+}